@@ -1,2 +1,25 @@
 pub mod fat;
 pub mod fat_entry;
+pub mod fat_boot;
+pub mod fat_table;
+pub mod fat_check;
+pub mod fat_repair;
+pub mod fat_reader;
+pub mod fat_walk;
+pub mod fat_json;
+pub mod fat_hash;
+pub mod fat_listing;
+pub mod fat_dfxml;
+pub mod fat_recover;
+pub mod fat_dump;
+pub mod fat_istat;
+pub mod fat_locate;
+pub mod fat_signature;
+pub mod fat_slack;
+pub mod fat_search;
+pub mod fat_hidden;
+pub mod fat_dirslack;
+pub mod fat_orphan;
+pub mod fat_assess;
+#[cfg(test)]
+mod fat_test;
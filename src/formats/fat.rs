@@ -1,21 +1,80 @@
 use memmap::{Mmap};
 use std::{
-    ffi::CString,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    io::{Error, ErrorKind},
 };
 use byteorder::{ByteOrder, LittleEndian};
 use super::fat_entry::*;
+use super::fat_boot::*;
+use super::fat_table::*;
+use super::fat_check::{self, Finding};
+use super::fat_recover;
+use super::fat_repair::{self, Repair, RepairOptions};
+use super::fat_walk::{Order, Walk, WalkOptions};
+use super::fat_json::{Region, VolumeInfo};
 
 // ###################### TRAITS #############################
 
 pub trait FAT {
-    fn tree(&self);
+    /// Returns the entries of the root directory
+    fn root_dir(&self) -> ReadDir;
+    /// Returns the byte offsets of the root directory (one per cluster on FAT32)
+    /// and the number of bytes stored at each offset
+    fn root_area(&self) -> (Vec<usize>, usize);
     fn info(&self);
+    fn fat(&self) -> &Fat;
+    fn select_fat(&mut self, fat: u8) -> std::io::Result<()>;
+    fn set_placeholder(&mut self, placeholder: char);
+    fn compare_fats(&self);
+    fn check(&self) -> Vec<Finding>;
+    fn repair(&self, options: &RepairOptions) -> Repair;
+
+    /// Returns the entries of a sub directory
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Directory entry of the sub directory
+    fn read_dir(&self, dir: &Entry) -> std::io::Result<ReadDir> {
+        if !dir.is_subdir_entry() || dir.is_disk_volume_entry() {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{} is not a directory", dir.name())));
+        }
+
+        Ok(ReadDir::new(self.fat().dir_entries(dir)))
+    }
+
+    /// Returns the entry at the given path (e.g. `/Pictures/my dog.jpg`)
+    ///
+    /// Path components are compared case-insensitively with the long and the
+    /// short name of every entry. Entries that aren't deleted are preferred.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Absolute path of the entry
+    fn stat(&self, path: &str) -> std::io::Result<Entry> {
+        let mut components = path.split('/').filter(|c| !c.is_empty()).peekable();
+        let mut entries: Vec<Entry> = self.root_dir().collect();
+
+        while let Some(component) = components.next() {
+            let entry = entries.iter()
+                .filter(|e| e.matches(component))
+                .min_by_key(|e| e.is_deleted())
+                .cloned()
+                .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{}: no such file or directory", path)))?;
+
+            if components.peek().is_none() {
+                return Ok(entry);
+            }
+
+            entries = self.read_dir(&entry)?.collect();
+        }
+
+        Err(Error::new(ErrorKind::InvalidInput, "the root directory has no directory entry"))
+    }
 }
 
 // ###################### STRUCTURES #########################
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// Represents a specific Cluster (not a range)
 pub struct Cluster(pub u32);
 
@@ -62,7 +121,21 @@ pub struct Fat {
     /// Offset to the root directory
     start_root_dir: Sector,
     /// Offset to the cluster area
-    start_cluster_area: Sector      
+    start_cluster_area: Sector,
+    /// The boot sector used to interpret the volume
+    boot: BootSector,
+    /// The backup boot sector (FAT32 only)
+    backup_boot: Option<BootSector>,
+    /// Is `boot` the backup boot sector? [yes/no]
+    boot_from_backup: bool,
+    /// The FSInfo sector (FAT32 only)
+    fs_info: Option<FsInfo>,
+    /// The backup FSInfo sector (FAT32 only)
+    backup_fs_info: Option<FsInfo>,
+    /// The FAT table used to resolve cluster chains
+    active_fat: u8,
+    /// Replaces the first character of deleted names that can't be restored
+    placeholder: char,
 }
 
 #[derive(Debug)]
@@ -82,22 +155,70 @@ pub struct Fat32 {
     root_clusters: Vec<Cluster>,
 }
 
+#[derive(Debug)]
+/// Iterator over the entries of a directory
+///
+/// The `.` and `..` entries are skipped, deleted entries are included.
+pub struct ReadDir {
+    entries: std::vec::IntoIter<Entry>,
+}
+
 // ###################### IMPLEMENTATIONS #########################
 
+impl dyn FAT + '_ {
+    /// Returns an iterator over all entries of the volume
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Traversal order and filters
+    pub fn walk(&self, options: WalkOptions) -> Walk<'_> {
+        Walk::new(self, options)
+    }
+
+    /// Display all directories in a tree like manner
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Filters applied to the displayed entries
+    pub fn tree(&self, options: WalkOptions) {
+        println!("File layout:\nDeleted = X, Disk Volume = V\nDirectory = D, File = F\n---------------------------------------");
+        for item in self.walk(WalkOptions { order: Order::DepthFirst, ..options }) {
+            println!("{}{}", "*".repeat(item.depth), item.entry);
+        }
+    }
+}
+
+impl ReadDir {
+    fn new(entries: Vec<Entry>) -> ReadDir {
+        let entries: Vec<Entry> = entries.into_iter()
+            .filter(|e| !e.is_this_entry() && !e.is_prev_entry())
+            .collect();
+        ReadDir { entries: entries.into_iter() }
+    }
+}
+
+impl Iterator for ReadDir {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        self.entries.next()
+    }
+}
+
 impl Fat {
     /// Size of a directory entry in bytes
     const DIR_ENTRY_SIZE: u16 = 32;
-    const EOF16: i16 = -1;
-    const EOF32: i32 = 0x0fffffff;
-    const BAD16: i16 = -9;
-    const BAD32: i32 = -9;
+    /// Default location of the backup boot sector (FAT32)
+    const DEFAULT_BACKUP_BOOT_SECTOR: usize = 6;
+    /// Default replacement of the first character of deleted names
+    const DEFAULT_PLACEHOLDER: char = '_';
     
     /// Convert a cluster number into a sector number
     ///
     /// # Arguments
     ///
     /// * `cluster` - Cluster number (must be >= 2)
-    fn cluster_to_sector(&self, cluster: &Cluster) -> Sector {
+    pub fn cluster_to_sector(&self, cluster: &Cluster) -> Sector {
         assert!(cluster.0 >= 2);
         Sector(((cluster.0 - 2) * self.sectors_per_cluster as u32) + self.start_cluster_area.0)
     }
@@ -107,7 +228,7 @@ impl Fat {
     /// # Arguments
     ///
     /// * `sector` - Sector number
-    fn sector_to_cluster(&self, sector: &Sector) -> Cluster {
+    pub fn sector_to_cluster(&self, sector: &Sector) -> Cluster {
         Cluster((sector.0 - self.start_cluster_area.0) / self.sectors_per_cluster as u32)
     }
     
@@ -116,18 +237,67 @@ impl Fat {
     /// # Arguments
     ///
     /// * `sector` - Secotr number that should be converted into an offset
-    fn offset(&self, sector: &Sector) -> usize {
+    pub fn offset(&self, sector: &Sector) -> usize {
         sector.0 as usize * self.bytes_per_sector as usize
     }
     
-    /// Returns a byte index into the FAT table that corresponds to the given cluster
+    /// Returns a byte index into the active FAT table that corresponds to the given cluster
     ///
     /// # Arguments
     ///
     /// * `cluster` - The n'th cluster to get the index for
     pub fn fat_table_offset(&self, cluster: &Cluster) -> usize {
         assert!(cluster.0 >= 2);
-        ((self.start_fat_area.0 * self.bytes_per_sector as u32) + (cluster.0 * (self.fat_table_entry_size / 8) as u32)) as usize
+        self.fat_table_offset_in(self.active_fat, cluster)
+    }
+
+    /// Returns a byte index into the given FAT table that corresponds to the given cluster
+    ///
+    /// # Arguments
+    ///
+    /// * `fat` - Number of the FAT table (0 for the first one)
+    /// * `cluster` - The n'th cluster to get the index for (0 and 1 are the reserved entries)
+    pub fn fat_table_offset_in(&self, fat: u8, cluster: &Cluster) -> usize {
+        let start = self.start_fat_area.0 + (fat as u32 * self.fat_table_sectors);
+        ((start * self.bytes_per_sector as u32) + (cluster.0 * (self.fat_table_entry_size / 8) as u32)) as usize
+    }
+
+    /// Select the FAT table that is used to resolve cluster chains
+    ///
+    /// By default FAT 0 is used, unless FAT mirroring is disabled on a FAT32
+    /// volume, in which case the active FAT designated by ExtFlags is used.
+    ///
+    /// # Arguments
+    ///
+    /// * `fat` - Number of the FAT table (0 for the first one)
+    pub fn select_fat(&mut self, fat: u8) -> std::io::Result<()> {
+        if fat >= self.fat_table_count {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                       format!("FAT {} doesn't exist (the volume has {} FAT tables)", fat, self.fat_table_count)));
+        }
+
+        self.active_fat = fat;
+        Ok(())
+    }
+
+    /// Returns the number of the FAT table that is used to resolve cluster chains
+    pub fn active_fat(&self) -> u8 {
+        self.active_fat
+    }
+
+    /// Select the character that replaces the first character of deleted
+    /// names if it can't be restored from the LFN entries (default: `_`)
+    ///
+    /// # Arguments
+    ///
+    /// * `placeholder` - The replacement character
+    pub fn set_placeholder(&mut self, placeholder: char) {
+        self.placeholder = placeholder;
+    }
+
+    /// Returns the character that replaces the first character of deleted names
+    pub fn placeholder(&self) -> char {
+        self.placeholder
     }
     
     /// Converts a vector of clusters into a vector of byte offsets
@@ -135,7 +305,7 @@ impl Fat {
     /// # Arguments
     ///
     /// * `clusters' - Vector of clusters
-    pub fn clusters_to_offsets(&self, clusters: &Vec<Cluster>) -> Vec<usize> {
+    pub fn clusters_to_offsets(&self, clusters: &[Cluster]) -> Vec<usize> {
         let mut offsets = Vec::new();
 
         for cluster in clusters {
@@ -146,46 +316,170 @@ impl Fat {
         offsets
     }
     
+    /// Returns the raw value of the FAT table entry of the given cluster
+    ///
+    /// Returns None if the entry lies beyond the end of the image (e.g. a
+    /// truncated image).
+    ///
+    /// # Arguments
+    ///
+    /// * `fat` - Number of the FAT table (0 for the first one)
+    /// * `cluster` - The cluster to read the entry for
+    pub fn read_table_entry(&self, fat: u8, cluster: &Cluster) -> Option<u32> {
+        let offset = self.fat_table_offset_in(fat, cluster);
+
+        if self.fat_table_entry_size == 16 {
+            self.mem.get(offset..offset + 2).map(|raw| LittleEndian::read_u16(raw) as u32)
+        } else {
+            self.mem.get(offset..offset + 4).map(LittleEndian::read_u32)
+        }
+    }
+
+    /// Returns the last cluster whose entry can be read from every FAT table
+    ///
+    /// This is less than the last cluster of the volume if the image is
+    /// truncated within the FAT area.
+    pub fn last_readable_cluster(&self) -> u32 {
+        let entry_size = std::cmp::max(self.fat_table_entry_size / 8, 1) as usize;
+        let start = self.fat_table_offset_in(self.fat_table_count.saturating_sub(1), &Cluster(0));
+        let entries = self.mem.len().saturating_sub(start) / entry_size;
+
+        std::cmp::min(self.total_clusters as usize, entries.saturating_sub(1)) as u32
+    }
+
+    /// Returns the memory mapping of the volume
+    pub fn mem(&self) -> &[u8] {
+        &self.mem
+    }
+
+    /// Returns the FAT type (FAT16, FAT32)
+    pub fn fat_type(&self) -> &str {
+        self.fat_type.trim()
+    }
+
+    /// Returns the total number of sectors of the volume
+    pub fn total_sectors(&self) -> u32 {
+        self.total_sectors
+    }
+
+    /// Returns the number of bytes per sector
+    pub fn bytes_per_sector(&self) -> u16 {
+        self.bytes_per_sector
+    }
+
+    /// Returns the number of sectors per cluster
+    pub fn sectors_per_cluster(&self) -> u8 {
+        self.sectors_per_cluster
+    }
+
+    /// Returns the highest valid cluster number
+    pub fn total_clusters(&self) -> u32 {
+        self.total_clusters
+    }
+
+    /// Returns the number of bits per FAT table entry (16 or 32)
+    pub fn fat_table_entry_size(&self) -> u8 {
+        self.fat_table_entry_size
+    }
+
+    /// Returns the number of FAT tables
+    pub fn fat_table_count(&self) -> u8 {
+        self.fat_table_count
+    }
+
+    /// Returns the boot sector used to interpret the volume
+    pub fn boot_sector(&self) -> &BootSector {
+        &self.boot
+    }
+
+    /// Returns the number of sectors of the reserved area
+    pub fn reserved_sectors(&self) -> u16 {
+        self.sectors_reserved_area
+    }
+
+    /// Returns the number of sectors per FAT table
+    pub fn fat_table_sectors(&self) -> u32 {
+        self.fat_table_sectors
+    }
+
+    /// Returns the first sector of the FAT area (FAT 0)
+    pub fn start_fat_area(&self) -> u32 {
+        self.start_fat_area.0
+    }
+
+    /// Returns the first sector of the root directory (of its first cluster on FAT32)
+    pub fn start_root_dir(&self) -> u32 {
+        self.start_root_dir.0
+    }
+
+    /// Returns the first sector of the cluster area (cluster 2)
+    pub fn start_cluster_area(&self) -> u32 {
+        self.start_cluster_area.0
+    }
+
+    /// Returns all clusters of the root directory (empty for FAT16)
+    pub fn root_clusters(&self) -> Vec<u32> {
+        if self.boot.is_fat32() {
+            self.get_cluster_chain(&Cluster(self.boot.root_cluster)).into_iter().map(|c| c.0).collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Checks if the volume wasn't unmounted cleanly
+    ///
+    /// The clean shutdown bit is stored in the FAT entry of cluster 1 (bit 15
+    /// for FAT16, bit 27 for FAT32) of the active FAT. A cleared bit means dirty, an
+    /// unreadable entry counts as clean.
+    pub fn is_dirty(&self) -> bool {
+        let clean = if self.fat_table_entry_size == 16 { 0x8000 } else { 0x0800_0000 };
+
+        self.read_table_entry(self.active_fat, &Cluster(1)).is_some_and(|v| v & clean == 0)
+    }
+
+    /// Checks if the given cluster number lies within the cluster area
+    pub fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster <= self.total_clusters
+    }
+
     /// Returns a Vector of Clusters that belong to a single file or directory
+    /// by following the chain through the active FAT
     ///
     /// # Arguments
     ///
     /// * `cluster` - First cluster of the cluster chain
-    ///
-    /// # FAT table Entry types
-    /// ## Fat16
-    /// 1. unused/ free cluster: 0x0000
-    /// 2. bad cluster: -9
-    /// 3. address of next cluster: n
-    /// 4. last cluster in a file (EOF): -1
-    ///
-    /// ## Fat32
-    /// 1. unused/ free cluster: 0x0000
-    /// 2. bad cluster: 0xfffffff7
-    /// 3. address of next cluster: n
-    /// 4. last cluster in a file (EOF): 0x0fffffff
     fn get_cluster_chain(&self, cluster: &Cluster) -> Vec<Cluster> {
+        self.get_cluster_chain_in(self.active_fat, cluster)
+    }
+
+    /// Returns a Vector of Clusters that belong to a single file or directory
+    /// by following the chain through the given FAT
+    ///
+    /// The chain ends at an EOF marker, a free or bad cluster or an invalid
+    /// cluster number. The first cluster is always part of the chain (if valid),
+    /// even if its FAT entry is free (e.g. for deleted files).
+    ///
+    /// # Arguments
+    ///
+    /// * `fat` - Number of the FAT table (0 for the first one)
+    /// * `cluster` - First cluster of the cluster chain
+    pub fn get_cluster_chain_in(&self, fat: u8, cluster: &Cluster) -> Vec<Cluster> {
         let mut clusters = Vec::new();
-        let mut offset;
+        let mut n = cluster.0;
 
-        if self.fat_table_entry_size == 16 {
-            let mut n = cluster.0 as i16;
+        while self.is_valid_cluster(n) && clusters.len() < self.total_clusters as usize {
+            clusters.push(Cluster(n));
 
-            while n != Fat::EOF16 && n != 0 && n != Fat::BAD16 {
-                let clu = Cluster(n as u32);
-                offset = self.fat_table_offset(&clu);
-                clusters.push(clu);
-                n = LittleEndian::read_i16(&self.mem[offset..offset+self.fat_table_entry_size as usize]);
-            }
-        } else {
-            let mut n = cluster.0 as i32;
+            // the entry lies beyond the end of a truncated image
+            let value = match self.read_table_entry(fat, &Cluster(n)) {
+                Some(value) => value,
+                None => break,
+            };
 
-            while n != Fat::EOF32 && n != 0 && n != Fat::BAD32 {
-                let clu = Cluster(n as u32);
-                offset = self.fat_table_offset(&clu);
-                clusters.push(clu);
-                n = LittleEndian::read_i32(&self.mem[offset..offset+self.fat_table_entry_size as usize]);
-            }
+            n = match TableEntry::new(value, self.fat_table_entry_size) {
+                TableEntry::Next(next) => next,
+                _ => break,
+            };
         }
 
         clusters
@@ -193,37 +487,108 @@ impl Fat {
     
     /// Returns a new Box pointer to a Fat16 or Fat32
     ///
+    /// The volume is interpreted using the primary boot sector. If the primary
+    /// boot sector is damaged, the backup boot sector (FAT32) is used instead.
+    ///
     /// # Arguments
     ///
     /// * `mem` - Mmap struct that holds the byte stream to parse
     ///
     /// # Examplse
     ///
-    /// ```
+    /// ```no_run
     /// use greasy::formats::fat;
-    /// use MmapOptions;
+    /// use memmap::MmapOptions;
     /// use std::fs::File;
     ///
-    /// let file = File::open("fat-16.dd")?;                    // open a fat volume
-    /// let mem = unsafe { MmapOptions::new().map(&file)? };    // map the volume into memory
+    /// let file = File::open("fat-16.dd").unwrap();                    // open a fat volume
+    /// let mem = unsafe { MmapOptions::new().map(&file).unwrap() };    // map the volume into memory
     ///
-    /// let fat = fat::Fat::new(mem);                           // create a new Fat object
+    /// let fat = fat::Fat::new(mem);                                   // create a new Fat object
     /// ```
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(mem: Mmap) -> Box<dyn FAT> {
-        let oem = CString::new(&mem[3..11]).expect("Parsing oem field for failed")
-                          .into_string().expect("Translation from CString to String failed");
+        let primary = BootSector::new(&mem[..BootSector::SIZE]);
 
-        let fat_table_sectors = match LittleEndian::read_u16(&mem[22..24]) {
-                0 => LittleEndian::read_u32(&mem[36..40]),                  // 0 indicates FAT32
-                _ => LittleEndian::read_u16(&mem[22..24]) as u32,
-        };
+        if !primary.is_valid() {
+            if let Some(backup) = Fat::find_backup_boot_sector(&mem, &primary) {
+                return Fat::build(mem, backup, true);
+            }
+        }
 
-        let fat_type = match LittleEndian::read_u16(&mem[22..24]) {
-                0 => CString::new(&mem[82..90]).expect("Parsing type field for FAT32 failed") // 0 indicates FAT32
-                        .into_string().expect("Translation from CString to String failed"),
-                _ => CString::new(&mem[54..62]).expect("Parsing type field for FAT12/16 failed")
-                        .into_string().expect("Translation from CString to String failed"),
-        };
+        Fat::build(mem, primary, false)
+    }
+
+    /// Returns a new Box pointer to a Fat16 or Fat32 that is interpreted using
+    /// the backup boot sector, even if the primary boot sector is intact.
+    ///
+    /// Falls back to the primary boot sector if no backup boot sector can be found.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - Mmap struct that holds the byte stream to parse
+    pub fn from_backup(mem: Mmap) -> Box<dyn FAT> {
+        let primary = BootSector::new(&mem[..BootSector::SIZE]);
+
+        match Fat::find_backup_boot_sector(&mem, &primary) {
+            Some(backup) => Fat::build(mem, backup, true),
+            None => Fat::build(mem, primary, false),
+        }
+    }
+
+    /// Search for a valid backup boot sector
+    ///
+    /// The location stored in the primary boot sector is tried first. Because the
+    /// primary might be damaged, the default location (sector 6) is tried as well
+    /// for all common sector sizes.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - The byte stream to search
+    /// * `primary` - The (possibly damaged) primary boot sector
+    fn find_backup_boot_sector(mem: &[u8], primary: &BootSector) -> Option<BootSector> {
+        let mut candidates = Vec::new();
+
+        if primary.backup_boot_sector != 0 && primary.backup_boot_sector != 0xffff {
+            candidates.push(primary.backup_boot_sector as usize * primary.bytes_per_sector as usize);
+        }
+
+        for bps in &[512, 1024, 2048, 4096] {
+            candidates.push(Fat::DEFAULT_BACKUP_BOOT_SECTOR * bps);
+        }
+
+        candidates.into_iter()
+                  .filter(|off| *off != 0 && off + BootSector::SIZE <= mem.len())
+                  .map(|off| (off, BootSector::new(&mem[off..off + BootSector::SIZE])))
+                  .find(|(off, b)| b.is_valid() && b.is_fat32() &&
+                                   *off == b.backup_boot_sector as usize * b.bytes_per_sector as usize)
+                  .map(|(_, b)| b)
+    }
+
+    /// Read the FSInfo sector that belongs to the boot sector at sector `boot`
+    fn read_fs_info(mem: &[u8], boot: &BootSector, base: u32) -> Option<FsInfo> {
+        if boot.fs_info_sector == 0 || boot.fs_info_sector == 0xffff {
+            return None;
+        }
+
+        let off = (base + boot.fs_info_sector as u32) as usize * boot.bytes_per_sector as usize;
+        if off + BootSector::SIZE > mem.len() {
+            return None;
+        }
+
+        Some(FsInfo::new(&mem[off..off + BootSector::SIZE]))
+    }
+
+    /// Build a Fat16 or Fat32 based on the given boot sector
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - Mmap struct that holds the byte stream to parse
+    /// * `boot` - The boot sector used to interpret the volume
+    /// * `boot_from_backup` - Whether `boot` is the backup boot sector
+    fn build(mem: Mmap, boot: BootSector, boot_from_backup: bool) -> Box<dyn FAT> {
+        let fat_table_sectors = boot.fat_size();
+        let fat_type = boot.fs_type.clone();
 
         let fat_table_entry_size = match fat_type.trim() {
                 "FAT16" => 16,
@@ -231,135 +596,307 @@ impl Fat {
                 _ => 0,
         };
 
-        let total_sectors = match LittleEndian::read_u16(&mem[19..21]) {
-                0 => LittleEndian::read_u32(&mem[32..36]),
-                _ => LittleEndian::read_u16(&mem[19..21]) as u32,
-        };
-
-        let sectors_reserved_area = LittleEndian::read_u16(&mem[14..16]);
-        let fat_table_count = mem[16] as u8;
-        let bytes_per_sector = LittleEndian::read_u16(&mem[11..13]);
-        let sectors_per_cluster = mem[13] as u8;
+        let total_sectors = boot.total_sectors();
+        let sectors_reserved_area = boot.reserved_sectors;
+        let fat_table_count = boot.fat_count;
+        let bytes_per_sector = boot.bytes_per_sector;
+        let sectors_per_cluster = boot.sectors_per_cluster;
 
         let sectors_fat_area = (fat_table_count as u32) * fat_table_sectors;
         let start_fat_area = sectors_reserved_area;
         let start_data_area = (start_fat_area as u32) + sectors_fat_area;
-        let total_root_entries = LittleEndian::read_u16(&mem[17..19]);
+        let total_root_entries = boot.root_entries;
         let start_cluster_area = match fat_type.trim() {
                 "FAT32" => start_data_area,
                 _ => start_data_area + ((total_root_entries * Fat::DIR_ENTRY_SIZE) / bytes_per_sector) as u32,
         };
-        let root_cluster = LittleEndian::read_u32(&mem[44..48]);
+        let root_cluster = boot.root_cluster;
         let start_root_dir = match fat_type.trim() {
                 "FAT32" => ((root_cluster - 2) * sectors_per_cluster as u32) + start_cluster_area,
                 _ => start_data_area,
         };
         let total_clusters = ((total_sectors - start_cluster_area) / sectors_per_cluster as u32) + 1;
 
+        let (backup_boot, fs_info, backup_fs_info) = if boot.is_fat32() {
+            let backup = Fat::find_backup_boot_sector(&mem, &boot);
+            let fs_info = Fat::read_fs_info(&mem, &boot, 0);
+            let backup_fs_info = Fat::read_fs_info(&mem, &boot, boot.backup_boot_sector as u32);
+            (backup, fs_info, backup_fs_info)
+        } else {
+            (None, None, None)
+        };
+
+        let active_fat = if boot.mirroring_disabled() && boot.active_fat() < fat_table_count {
+            boot.active_fat()
+        } else {
+            0
+        };
 
         let f = Fat {
-            oem: oem,
-            fat_table_sectors: fat_table_sectors,
-            fat_type: fat_type,
-            fat_table_entry_size: fat_table_entry_size,
-            fat_table_count: fat_table_count,
-            bytes_per_sector: bytes_per_sector,
-            sectors_per_cluster: sectors_per_cluster,
-            total_sectors: total_sectors,
-            sectors_reserved_area: sectors_reserved_area,
+            oem: boot.oem.clone(),
+            fat_table_sectors,
+            fat_type,
+            fat_table_entry_size,
+            fat_table_count,
+            bytes_per_sector,
+            sectors_per_cluster,
+            total_sectors,
+            sectors_reserved_area,
             start_reserved_area: Sector(0),
-            sectors_fat_area: sectors_fat_area,
+            sectors_fat_area,
             start_fat_area: Sector(start_fat_area as u32),
             start_data_area: Sector(start_data_area),
             start_root_dir: Sector(start_root_dir),
             start_cluster_area: Sector(start_cluster_area),
-            total_clusters: total_clusters,
-            mem: mem,
+            total_clusters,
+            boot,
+            backup_boot,
+            boot_from_backup,
+            fs_info,
+            backup_fs_info,
+            active_fat,
+            placeholder: Fat::DEFAULT_PLACEHOLDER,
+            mem,
         };
 
         if f.fat_type.trim() == "FAT16" {
-            return Box::new(Fat16{fat: f, total_root_entries: total_root_entries});
+            Box::new(Fat16{fat: f, total_root_entries})
         } else {
-            return Box::new(Fat32{fat: f, root_clusters: vec![Cluster(root_cluster)]});
+            Box::new(Fat32{fat: f, root_clusters: vec![Cluster(root_cluster)]})
         }
     }
     
-    /// Parse and display entries of a directory and it's sub directories
-    /// recursively.
+    /// Parse the entries of a single directory
+    ///
+    /// LFN entries are attached to the entry they belong to (after restoring
+    /// the first character of deleted names) and the cluster chain of each
+    /// entry is resolved.
     ///
     /// # Arguments
     ///
     /// * `offset` - Vector of byte offsets to the different clusters of a directory
     /// * `max` - Maximum number of bytes per cluster
-    /// * 'indentation' - Indentation level
-    ///
-    /// There is only one offset if the fat is of type fat16 and it has a max size of
-    /// <total_root_entries * Fat::DIR_ENTRY_SIZE>.
-    fn _tree(&self, offset: Vec<usize>, max: usize, indentation: u8) {
+    fn _dir(&self, offset: Vec<usize>, max: usize) -> Vec<Entry> {
         let mut files: Vec<Entry> = Vec::new();
         let mut lfns: HashMap<u8, Vec<LFNEntry>> = HashMap::new();
         let mut i: usize;
-        let mut next; 
-        let mut indent_str = String::new();
-        
-        // build indentation string
-        for _x in 0..indentation {
-            indent_str.push_str("*");
-        }
 
         // iterate over each cluster offset of the current dir
         for coff in offset {   
             i = 0;
 
-            while self.mem[coff + i] != 0 && i < max {
-                next = i + Fat::DIR_ENTRY_SIZE as usize;
+            while i < max {
+                // slots beyond the end of a truncated image can't be read
+                let slot = match self.mem.get(coff + i..coff + i + Fat::DIR_ENTRY_SIZE as usize) {
+                    Some(slot) if slot[0] != 0 => slot,
+                    _ => break,
+                };
 
-                if LFNEntry::is_lfn_entry(self.mem[coff + i + 11]) {
-                    let lfn_entry = LFNEntry::new(&self.mem[coff+i..coff+next]);
-                    let lfn_vec = lfns.entry(lfn_entry.checksum()).or_insert(Vec::new());
+                if LFNEntry::is_lfn_entry(slot[11]) {
+                    let lfn_entry = LFNEntry::new(slot);
+                    let lfn_vec = lfns.entry(lfn_entry.checksum()).or_default();
                     lfn_vec.push(lfn_entry);
                 } else {
-                    let entry = Entry::new(&self.mem[coff+i..coff+next]);
+                    let mut entry = Entry::new(slot);
+                    entry.add_address(coff + i);
                     files.push(entry);
                 }
 
-                i = next;
+                i += Fat::DIR_ENTRY_SIZE as usize;
             }
 
-            if self.mem[coff + i] != 0 {
+            if i < max {
                 break;
             }
         }
 
         for e in &mut files {
+            e.restore_name(&lfns, self.placeholder);
             e.add_lfn(&mut lfns);
-            e.add_clusters(self.get_cluster_chain(&e.start()));
-            
 
-            if e.is_this_entry() == false && e.is_prev_entry() == false {
-                print!("{}", indent_str);
-                println!("{}", e.to_string());
+            // the chain of a deleted directory is gone, its clusters are presumed
+            if e.is_subdir_entry() && !e.is_this_entry() && !e.is_prev_entry() && fat_recover::is_unlinked(self, e) {
+                e.add_clusters(fat_recover::presumed_dir_clusters(self, e));
+            } else {
+                e.add_clusters(self.get_cluster_chain(e.start()));
+            }
+        }
+
+        files
+    }
+
+    /// Parse the entries of a directory and it's sub directories recursively
+    /// and collect them together with their path.
+    ///
+    /// Deleted sub directories are collected, but not descended into, so they
+    /// can't hide a live directory that reuses their start cluster.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Vector of byte offsets to the different clusters of a directory
+    /// * `max` - Maximum number of bytes per cluster
+    /// * `path` - Path of the directory
+    /// * `visited` - Start clusters of all directories visited so far
+    /// * `files` - Collected (path, entry) pairs
+    fn _files(&self, offset: Vec<usize>, max: usize, path: &str, visited: &mut HashSet<u32>, files: &mut Vec<(String, Entry)>) {
+        for e in self._dir(offset, max) {
+            if e.is_this_entry() || e.is_prev_entry() {
+                continue;
             }
 
-            if e.is_subdir_entry() && e.is_this_entry() == false && e.is_prev_entry() == false {
-                match e.clusters() {
-                    Some(clu) => self._tree(self.clusters_to_offsets(clu), (self.bytes_per_sector * (self.sectors_per_cluster as u16)) as usize, indentation + 1),
-                    None => (),
-                };
+            let entry_path = format!("{}/{}", path, e.name());
+
+            if e.is_subdir_entry() && !e.is_disk_volume_entry() && !e.is_deleted() && visited.insert(e.start().0) {
+                if let Some(clu) = e.clusters() {
+                    self._files(self.clusters_to_offsets(clu), self.cluster_size(), &entry_path, visited, files);
+                }
             }
+
+            files.push((entry_path, e));
         }
     }
-    
+
+    /// Returns the entries of a sub directory
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Directory entry of the sub directory
+    pub fn dir_entries(&self, dir: &Entry) -> Vec<Entry> {
+        match dir.clusters() {
+            Some(clu) => self._dir(self.clusters_to_offsets(clu), self.cluster_size()),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the size of a cluster in bytes
+    pub fn cluster_size(&self) -> usize {
+        self.bytes_per_sector as usize * self.sectors_per_cluster as usize
+    }
+
+    /// Compare all FAT copies with FAT 0 entry by entry
+    ///
+    /// Differing entries of consecutive clusters are grouped into runs and every
+    /// run is mapped to the files whose cluster chain (in any FAT copy) touches it.
+    /// Clusters whose entries lie beyond the end of a truncated image aren't
+    /// compared (see last_readable_cluster()).
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - All (path, entry) pairs of the volume
+    pub fn fat_divergences(&self, files: &[(String, Entry)]) -> Vec<Divergence> {
+        let mut runs: Vec<Divergence> = Vec::new();
+
+        for c in 2..=self.last_readable_cluster() {
+            let cluster = Cluster(c);
+            let values: Vec<u32> = (0..self.fat_table_count).filter_map(|f| self.read_table_entry(f, &cluster)).collect();
+
+            if values.iter().all(|v| *v == values[0]) {
+                continue;
+            }
+
+            match runs.last_mut() {
+                Some(run) if run.last + 1 == c => {
+                    run.last = c;
+                    run.entries.push(values);
+                },
+                _ => runs.push(Divergence { first: c, last: c, entries: vec![values], files: Vec::new() }),
+            }
+        }
+
+        if runs.is_empty() {
+            return runs;
+        }
+
+        for (path, e) in files {
+            let mut clusters = HashSet::new();
+            for f in 0..self.fat_table_count {
+                clusters.extend(self.get_cluster_chain_in(f, e.start()).into_iter().map(|c| c.0));
+            }
+
+            for run in &mut runs {
+                if clusters.iter().any(|c| *c >= run.first && *c <= run.last) {
+                    run.files.push(path.clone());
+                }
+            }
+        }
+
+        runs
+    }
+
+    /// Collect all entries of the volume together with their path
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Vector of byte offsets to the different clusters of the root directory
+    /// * `max` - Maximum number of bytes per cluster of the root directory
+    fn files(&self, offset: Vec<usize>, max: usize) -> Vec<(String, Entry)> {
+        let mut files = Vec::new();
+        self._files(offset, max, "", &mut HashSet::new(), &mut files);
+        files
+    }
+
+    /// Display a report about all clusters whose FAT entries differ between the FAT copies
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Vector of byte offsets to the different clusters of the root directory
+    /// * `max` - Maximum number of bytes per cluster of the root directory
+    fn compare_fats(&self, offset: Vec<usize>, max: usize) {
+        println!("FAT COMPARISON\n--------------------------------");
+
+        if self.fat_table_count < 2 {
+            println!("The volume has only one FAT table\n");
+            return;
+        }
+
+        let runs = self.fat_divergences(&self.files(offset, max));
+
+        let last = self.last_readable_cluster();
+        if last < self.total_clusters {
+            println!("WARNING: the image is truncated, the FAT entries of clusters {} - {} can't be compared\n",
+                     last + 1, self.total_clusters);
+        }
+
+        if runs.is_empty() {
+            println!("All {} FAT tables are identical\n", self.fat_table_count);
+            return;
+        }
+
+        println!("{} divergent run(s), {} cluster(s) differ\n", runs.len(),
+                 runs.iter().map(|r| r.entries.len()).sum::<usize>());
+
+        for run in &runs {
+            println!("Clusters {} - {}", run.first, run.last);
+
+            for (i, values) in run.entries.iter().enumerate() {
+                let decoded: Vec<String> = values.iter()
+                    .enumerate()
+                    .map(|(f, v)| format!("FAT {}: {}", f, TableEntry::new(*v, self.fat_table_entry_size)))
+                    .collect();
+                println!("|- {}: {}", run.first as usize + i, decoded.join(" | "));
+            }
+
+            if run.files.is_empty() {
+                println!("└─ Affected files: none\n");
+            } else {
+                println!("└─ Affected files: {}\n", run.files.join(", "));
+            }
+        }
+    }
+
     /// Display general information about the file system
     pub fn info(&self) {
         println!("FILE SYSTEM INFORMATION
 --------------------------------
 File System Type: {}
 OEM Name: {}
-Vloume ID:
-Volume Label (Boot Sector):
+Volume ID: 0x{:08x}
+Volume Label (Boot Sector): {}
 File System Type Label: {}
 
+Boot Sector
+--------------------------------
+{}
 Size
 --------------------------------
 Sector Size (in bytes): {}
@@ -369,21 +906,35 @@ Cluster Range: 2 - {}
 File System Layout (in sectors)
 --------------------------------
 Total Sector Range: 0 - {}
-|- Reserved: 0 - {}
-|  └─ Boot Sector: 0",
+|- Reserved: {} - {}",
         self.fat_type,
         self.oem,
+        self.boot.volume_id,
+        self.boot.volume_label,
         self.fat_type,
+        self.boot_sector_info(),
         self.bytes_per_sector,
         self.bytes_per_sector * (self.sectors_per_cluster as u16),
         self.total_clusters,
         self.total_sectors - 1,
+        self.start_reserved_area.0,
         self.sectors_reserved_area - 1,
         );
 
+        if self.boot.is_fat32() {
+            println!("|  |- Boot Sector: 0");
+            println!("|  |- FSInfo: {}", self.boot.fs_info_sector);
+            println!("|  └─ Backup Boot Sector: {}", self.boot.backup_boot_sector);
+        } else {
+            println!("|  └─ Boot Sector: 0");
+        }
+
+        println!("|- FAT Area: {} - {}", self.start_fat_area.0, self.start_fat_area.0 + self.sectors_fat_area - 1);
+
         for i in 0..self.fat_table_count as u32 {
-            println!("|- FAT {}: {} - {}", i, self.start_fat_area.0 + (i * self.fat_table_sectors), 
-                     self.start_fat_area.0 + ((i+1) * self.fat_table_sectors) - 1);
+            println!("|  |- FAT {}: {} - {}{}", i, self.start_fat_area.0 + (i * self.fat_table_sectors), 
+                     self.start_fat_area.0 + ((i+1) * self.fat_table_sectors) - 1,
+                     if i == self.active_fat as u32 { " (active)" } else { "" });
         }
 
         println!("└─ Data Area: {} - {}", self.start_data_area.0, self.total_sectors - 1);
@@ -396,31 +947,215 @@ Total Sector Range: 0 - {}
             println!("    └─ Cluster Area: {} - {}", self.start_cluster_area.0, self.total_sectors - 1);
         }
 
+        if self.boot.is_fat32() {
+            self.backup_info();
+        }
+
         println!("\n");
     }
+
+    /// Returns the information displayed by info() as structured data
+    pub fn volume_info(&self) -> VolumeInfo {
+        let mut reserved = Region::new("Reserved", self.start_reserved_area.0, self.sectors_reserved_area as u32 - 1);
+        reserved.regions.push(Region::new("Boot Sector", 0, 0));
+        if self.boot.is_fat32() {
+            let fs_info = self.boot.fs_info_sector as u32;
+            let backup = self.boot.backup_boot_sector as u32;
+            reserved.regions.push(Region::new("FSInfo", fs_info, fs_info));
+            reserved.regions.push(Region::new("Backup Boot Sector", backup, backup));
+        }
+
+        let mut fat_area = Region::new("FAT Area", self.start_fat_area.0, self.start_fat_area.0 + self.sectors_fat_area - 1);
+        for i in 0..self.fat_table_count as u32 {
+            let first = self.start_fat_area.0 + (i * self.fat_table_sectors);
+            fat_area.regions.push(Region::new(&format!("FAT {}", i), first, first + self.fat_table_sectors - 1));
+        }
+
+        let mut data_area = Region::new("Data Area", self.start_data_area.0, self.total_sectors - 1);
+        let mut cluster_area = Region::new("Cluster Area", self.start_cluster_area.0, self.total_sectors - 1);
+        if self.boot.is_fat32() {
+            cluster_area.regions.push(Region::new("Root", self.start_root_dir.0, self.start_root_dir.0));
+            data_area.regions.push(cluster_area);
+        } else {
+            data_area.regions.push(Region::new("Root", self.start_root_dir.0, self.start_cluster_area.0 - 1));
+            data_area.regions.push(cluster_area);
+        }
+
+        let primary = BootSector::new(&self.mem[..BootSector::SIZE]);
+
+        VolumeInfo {
+            fat_type: self.fat_type.trim().to_string(),
+            oem: self.oem.clone(),
+            volume_id: self.boot.volume_id,
+            volume_label: self.boot.volume_label.clone(),
+            boot_sector: self.boot.clone(),
+            boot_sector_warnings: self.boot.warnings(),
+            boot_from_backup: self.boot_from_backup,
+            sector_size: self.bytes_per_sector,
+            cluster_size: self.cluster_size(),
+            last_cluster: self.total_clusters,
+            active_fat: self.active_fat,
+            dirty: self.is_dirty(),
+            layout: vec![reserved, fat_area, data_area],
+            fs_info: self.fs_info.clone(),
+            backup_boot_sector: self.backup_boot.clone(),
+            backup_fs_info: self.backup_fs_info.clone(),
+            boot_sector_diff: self.backup_boot.as_ref().map(|b| primary.diff(b)).unwrap_or_default(),
+            fs_info_diff: match (&self.fs_info, &self.backup_fs_info) {
+                (Some(primary), Some(backup)) => primary.diff(backup),
+                _ => Vec::new(),
+            },
+        }
+    }
+
+    /// Returns every field of the boot sector followed by a warning for each
+    /// invalid value (one per line)
+    fn boot_sector_info(&self) -> String {
+        let mut s = String::new();
+
+        for (field, value) in self.boot.fields() {
+            s.push_str(&format!("{}: {}\n", field, value));
+        }
+
+        for warning in self.boot.warnings() {
+            s.push_str(&format!("WARNING: {}\n", warning));
+        }
+
+        s
+    }
+
+    /// Display the result of comparing the primary with the backup boot sector
+    /// and the primary with the backup FSInfo sector
+    fn backup_info(&self) {
+        println!("\nBackup Boot Sector
+--------------------------------");
+
+        let primary = BootSector::new(&self.mem[..BootSector::SIZE]);
+
+        if self.boot_from_backup {
+            println!("WARNING: primary boot sector is damaged, using backup boot sector {}", self.boot.backup_boot_sector);
+            for warning in primary.warnings() {
+                println!("WARNING: primary {}", warning);
+            }
+        }
+        match &self.backup_boot {
+            Some(backup) => Fat::print_diff("Boot Sector", &primary.diff(backup)),
+            None => println!("Boot Sector: no valid backup found"),
+        }
+
+        match (&self.fs_info, &self.backup_fs_info) {
+            (Some(primary), Some(backup)) => {
+                if !primary.is_valid() {
+                    println!("WARNING: primary FSInfo has invalid signatures");
+                }
+                if !backup.is_valid() {
+                    println!("WARNING: backup FSInfo has invalid signatures");
+                }
+                Fat::print_diff("FSInfo", &primary.diff(backup));
+            },
+            _ => println!("FSInfo: no backup found"),
+        }
+    }
+
+    /// Display a list of differing fields
+    fn print_diff(name: &str, diff: &[FieldDiff]) {
+        if diff.is_empty() {
+            println!("{}: backup is identical to primary", name);
+        } else {
+            println!("{}: {} field(s) differ", name, diff.len());
+            for d in diff {
+                println!("|- {}: {} (primary) / {} (backup)", d.field, d.primary, d.backup);
+            }
+        }
+    }
     
 }
 
 impl FAT for Fat16 {
-    fn tree(&self) {
-        println!("File layout:\nDeleted = X, Disk Volume = V\nDirectory = D, File = F\n---------------------------------------");
-        let offset = self.fat.offset(&self.fat.start_root_dir);
-        self.fat._tree(vec![offset], (self.total_root_entries * Fat::DIR_ENTRY_SIZE) as usize, 1);
+    fn root_dir(&self) -> ReadDir {
+        let (offset, max) = self.root_area();
+        ReadDir::new(self.fat._dir(offset, max))
+    }
+
+    /// Returns the byte offset and the size (in bytes) of the root directory
+    fn root_area(&self) -> (Vec<usize>, usize) {
+        (vec![self.fat.offset(&self.fat.start_root_dir)], (self.total_root_entries * Fat::DIR_ENTRY_SIZE) as usize)
     }
 
 
     fn info(&self) {
         self.fat.info();
     }
+
+    fn fat(&self) -> &Fat {
+        &self.fat
+    }
+
+    fn select_fat(&mut self, fat: u8) -> std::io::Result<()> {
+        self.fat.select_fat(fat)
+    }
+
+    fn set_placeholder(&mut self, placeholder: char) {
+        self.fat.set_placeholder(placeholder);
+    }
+
+    fn compare_fats(&self) {
+        let (offset, max) = self.root_area();
+        self.fat.compare_fats(offset, max);
+    }
+
+    fn check(&self) -> Vec<Finding> {
+        let (offset, max) = self.root_area();
+        fat_check::check(&self.fat, &self.fat.files(offset, max))
+    }
+
+    fn repair(&self, options: &RepairOptions) -> Repair {
+        let (offset, max) = self.root_area();
+        fat_repair::plan(&self.fat, &self.fat.files(offset.clone(), max), &offset, max, options)
+    }
 }
 
 impl FAT for Fat32 {
-    fn tree(&self) {
-        println!("Not implemented for Fat32 yet!");
+    fn root_dir(&self) -> ReadDir {
+        let (offset, max) = self.root_area();
+        ReadDir::new(self.fat._dir(offset, max))
+    }
+
+    /// Returns the byte offsets of all clusters of the root directory and the cluster size
+    fn root_area(&self) -> (Vec<usize>, usize) {
+        let clusters = self.fat.get_cluster_chain(&self.root_clusters[0]);
+        (self.fat.clusters_to_offsets(&clusters), self.fat.cluster_size())
     }
 
     fn info(&self) {
         self.fat.info();
     }
+
+    fn fat(&self) -> &Fat {
+        &self.fat
+    }
+
+    fn select_fat(&mut self, fat: u8) -> std::io::Result<()> {
+        self.fat.select_fat(fat)
+    }
+
+    fn set_placeholder(&mut self, placeholder: char) {
+        self.fat.set_placeholder(placeholder);
+    }
+
+    fn compare_fats(&self) {
+        let (offset, max) = self.root_area();
+        self.fat.compare_fats(offset, max);
+    }
+
+    fn check(&self) -> Vec<Finding> {
+        let (offset, max) = self.root_area();
+        fat_check::check(&self.fat, &self.fat.files(offset, max))
+    }
+
+    fn repair(&self, options: &RepairOptions) -> Repair {
+        let (offset, max) = self.root_area();
+        fat_repair::plan(&self.fat, &self.fat.files(offset.clone(), max), &offset, max, options)
+    }
 }
 
@@ -0,0 +1,201 @@
+use super::fat::*;
+use super::fat_entry::*;
+use super::fat_locate::ClusterIndex;
+use super::fat_recover;
+use super::fat_signature::{Signature, SIGNATURES};
+use super::fat_walk::{DeletedDirs, WalkOptions};
+use std::{cmp, fmt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How much of a deleted entry's data presumably survived
+pub enum Status {
+    /// None of the presumed clusters has been allocated again and the first
+    /// cluster matches the signature of the file extension (if known)
+    Intact,
+    /// Some of the presumed clusters have been allocated again or the first
+    /// cluster doesn't match the signature of the file extension
+    PartiallyOverwritten,
+    /// All of the presumed clusters have been allocated again (or the data
+    /// can't be located anymore)
+    Overwritten,
+}
+
+#[derive(Debug, Clone)]
+/// Recoverability of a single deleted entry
+pub struct Assessment {
+    /// Absolute path of the entry
+    pub path: String,
+    pub entry: Entry,
+    /// The clusters the entry presumably occupied
+    pub clusters: Vec<u32>,
+    /// Presumed clusters that are now part of the chain of a live entry
+    pub reallocated: Vec<u32>,
+    /// Does the first cluster start with a signature of the file extension?
+    /// (None if no signature is known for the extension)
+    pub signature: Option<bool>,
+    pub status: Status,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Intact => write!(f, "intact"),
+            Status::PartiallyOverwritten => write!(f, "partially overwritten"),
+            Status::Overwritten => write!(f, "overwritten"),
+        }
+    }
+}
+
+impl fmt::Display for Assessment {
+    /// Formats the assessment as a single tab separated line: status,
+    /// reallocated/presumed clusters, signature (match, mismatch or -) and path
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let signature = match self.signature {
+            Some(true) => "match",
+            Some(false) => "mismatch",
+            None => "-",
+        };
+        write!(f, "{}\t{}/{}\t{}\t{}", self.status, self.reallocated.len(), self.clusters.len(), signature, self.path)
+    }
+}
+
+/// Returns the signatures expected for the extension of a file name
+///
+/// # Arguments
+///
+/// * `name` - The (long or short) file name
+///
+/// # Examples
+///
+/// ```
+/// use greasy::formats::fat_assess::expected_signatures;
+///
+/// assert_eq!("JPEG", expected_signatures("my dog.jpg")[0].name);
+/// assert_eq!(2, expected_signatures("SCAN.TIF").len());
+/// assert!(expected_signatures("notes.txt").is_empty());
+/// assert!(expected_signatures("README").is_empty());
+/// ```
+pub fn expected_signatures(name: &str) -> Vec<&'static Signature> {
+    let extension = match name.rsplit_once('.') {
+        Some((_, ext)) if !ext.is_empty() => ext.to_ascii_uppercase(),
+        _ => return Vec::new(),
+    };
+
+    SIGNATURES.iter().filter(|s| s.extensions.contains(&extension.as_str())).collect()
+}
+
+/// Assess the recoverability of a single deleted entry
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+/// * `index` - Owners of every cluster
+/// * `path` - Absolute path of the entry
+/// * `e` - The deleted entry
+pub fn assess_entry(fat: &Fat, index: &ClusterIndex, path: &str, e: &Entry) -> Assessment {
+    let clusters: Vec<u32> = fat_recover::presumed_clusters(fat, e).iter().map(|c| c.0).collect();
+    let reallocated: Vec<u32> = clusters.iter()
+        .filter(|c| index.owners(**c).iter().any(|o| !o.deleted && o.path != path))
+        .copied()
+        .collect();
+
+    let expected = expected_signatures(&e.name());
+    let signature = match clusters.first() {
+        Some(c) if !e.is_subdir_entry() && !expected.is_empty() => {
+            let start = fat.clusters_to_offsets(&[Cluster(*c)])[0];
+            let end = cmp::min(start + fat.cluster_size(), fat.mem().len());
+            let data = fat.mem().get(start..end).unwrap_or_default();
+            Some(expected.iter().any(|s| s.matches(data)))
+        },
+        _ => None,
+    };
+
+    // empty files have no data that could be overwritten
+    let no_data = !e.is_subdir_entry() && e.size() == 0;
+    let status = if no_data {
+        Status::Intact
+    } else if clusters.is_empty() || reallocated.len() == clusters.len() {
+        Status::Overwritten
+    } else if !reallocated.is_empty() || signature == Some(false) {
+        Status::PartiallyOverwritten
+    } else {
+        Status::Intact
+    };
+
+    Assessment { path: path.to_string(), entry: e.clone(), clusters, reallocated, signature, status }
+}
+
+/// Assess the recoverability of every deleted entry of the volume
+///
+/// Entries within deleted directories are assessed as well. Presumed
+/// clusters are compared with the chains of all live entries, and the first
+/// cluster is checked against the signatures of the file extension.
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+pub fn assess(fat: &dyn FAT) -> Vec<Assessment> {
+    let base = fat.fat();
+    let index = ClusterIndex::new(fat);
+    let mut deleted = DeletedDirs::default();
+
+    fat.walk(WalkOptions::default())
+        .filter(|item| deleted.is_deleted(item) || fat_recover::is_unlinked(base, &item.entry))
+        .filter(|item| !item.entry.is_disk_volume_entry())
+        .map(|item| assess_entry(base, &index, &item.path, &item.entry))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fat_test::*;
+
+    fn lines(image: &Image) -> Vec<String> {
+        assess(&*image.open()).iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn rates_deleted_entries() {
+        let mut image = Image::new();
+        image.set_root(0, &[
+            short_entry(b"\xe5HOTO   JPG", 0x20, 3, 100),
+            short_entry(b"\xe5CAN    JPG", 0x20, 4, 100),
+            short_entry(b"\xe5EPORT  TXT", 0x20, 5, 2048),
+            short_entry(b"\xe5LD     TXT", 0x20, 7, 100),
+            short_entry(b"\xe5MPTY   TXT", 0x20, 0, 0),
+            short_entry(b"LIVE    TXT", 0x20, 6, 2048),
+        ]);
+        image.write(Image::cluster_offset(3), b"\xff\xd8\xff\xe0");
+        image.write(Image::cluster_offset(4), b"not a jpeg");
+        // LIVE.TXT took the second cluster of REPORT.TXT and the only one of OLD.TXT
+        image.set_chain(&[6, 7]);
+
+        assert_eq!(lines(&image), vec![
+            "intact\t0/1\tmatch\t/_HOTO.JPG",
+            "partially overwritten\t0/1\tmismatch\t/_CAN.JPG",
+            "partially overwritten\t1/2\t-\t/_EPORT.TXT",
+            "overwritten\t1/1\t-\t/_LD.TXT",
+            "intact\t0/0\t-\t/_MPTY.TXT",
+        ]);
+    }
+
+    #[test]
+    fn rates_entries_of_deleted_directories() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"\xe5IR        ", 0x10, 3, 0), short_entry(b"LIVE    TXT", 0x20, 5, 100)]);
+        image.set_dir(3, 0, &[
+            short_entry(b".          ", 0x10, 3, 0),
+            short_entry(b"..         ", 0x10, 0, 0),
+            short_entry(b"KEPT    TXT", 0x20, 4, 100),
+            short_entry(b"LOST    TXT", 0x20, 5, 100),
+        ]);
+        image.set_chain(&[5]);
+
+        assert_eq!(lines(&image), vec![
+            "intact\t0/1\t-\t/_IR",
+            "intact\t0/1\t-\t/_IR/KEPT.TXT",
+            "overwritten\t1/1\t-\t/_IR/LOST.TXT",
+        ]);
+    }
+}
@@ -0,0 +1,423 @@
+use byteorder::{ByteOrder, LittleEndian};
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// BootSector represents the BIOS Parameter Block (BPB) of a FAT volume
+///
+/// The first sector of every FAT volume holds the boot sector. FAT32
+/// volumes additionally keep a backup copy of it within the reserved area
+/// (usually at sector 6). Fields that only exist on FAT32 volumes are
+/// zero for FAT16.
+pub struct BootSector {
+    /// Jump instruction to the boot code
+    pub jump: [u8; 3],
+    /// Original equipment manufacturer label
+    pub oem: String,
+    /// Number of Bytes per sector (512, 1024, ...)
+    pub bytes_per_sector: u16,
+    /// Number of sectors per cluster
+    pub sectors_per_cluster: u8,
+    /// Number of sectors belonging to the reserved area
+    pub reserved_sectors: u16,
+    /// Number of FAT tables
+    pub fat_count: u8,
+    /// Maximum number of entries in the root directory (0 for FAT32)
+    pub root_entries: u16,
+    /// Total number of sectors if it fits into 16 bits
+    pub total_sectors_16: u16,
+    /// Media descriptor
+    pub media: u8,
+    /// Number of sectors per FAT table (0 for FAT32)
+    pub fat_size_16: u16,
+    /// Number of sectors per track
+    pub sectors_per_track: u16,
+    /// Number of heads
+    pub heads: u16,
+    /// Number of sectors before the start of the volume
+    pub hidden_sectors: u32,
+    /// Total number of sectors if it doesn't fit into 16 bits
+    pub total_sectors_32: u32,
+    /// Number of sectors per FAT table (FAT32)
+    pub fat_size_32: u32,
+    /// Mirroring flags and active FAT (FAT32)
+    pub ext_flags: u16,
+    /// File system version (FAT32)
+    pub fs_version: u16,
+    /// First cluster of the root directory (FAT32)
+    pub root_cluster: u32,
+    /// Sector number of the FSInfo structure (FAT32)
+    pub fs_info_sector: u16,
+    /// Sector number of the backup boot sector (FAT32)
+    pub backup_boot_sector: u16,
+    /// BIOS drive number
+    pub drive_number: u8,
+    /// Extended boot signature (0x29 if the following three fields are valid)
+    pub ext_boot_signature: u8,
+    /// Volume serial number
+    pub volume_id: u32,
+    /// Volume label
+    pub volume_label: String,
+    /// File system type label
+    pub fs_type: String,
+    /// Boot sector signature (0xaa55)
+    pub signature: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// FsInfo represents the FAT32 file system information sector
+pub struct FsInfo {
+    /// Lead signature (0x41615252)
+    pub lead_signature: u32,
+    /// Structure signature (0x61417272)
+    pub struct_signature: u32,
+    /// Last known number of free clusters (0xffffffff if unknown)
+    pub free_count: u32,
+    /// Hint where to look for the next free cluster (0xffffffff if unknown)
+    pub next_free: u32,
+    /// Trail signature (0xaa550000)
+    pub trail_signature: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+/// A single field that differs between two copies of the same structure
+pub struct FieldDiff {
+    /// Name of the field
+    pub field: &'static str,
+    /// Value found in the primary copy
+    pub primary: String,
+    /// Value found in the backup copy
+    pub backup: String,
+}
+
+/// Compare two lists of named fields and return the ones that differ
+///
+/// Fields that only exist in one of the lists are reported with the value `-`
+/// for the other one.
+fn diff_fields(primary: Vec<(&'static str, String)>, backup: Vec<(&'static str, String)>) -> Vec<FieldDiff> {
+    let mut diffs = Vec::new();
+
+    for (field, value) in &primary {
+        let other = backup.iter().find(|(f, _)| f == field).map(|(_, v)| v.clone());
+        if other.as_ref() != Some(value) {
+            diffs.push(FieldDiff { field, primary: value.clone(), backup: other.unwrap_or_else(|| "-".to_string()) });
+        }
+    }
+
+    for (field, value) in backup {
+        if !primary.iter().any(|(f, _)| *f == field) {
+            diffs.push(FieldDiff { field, primary: "-".to_string(), backup: value });
+        }
+    }
+
+    diffs
+}
+
+/// Interpret a fixed size label field
+fn label(mem: &[u8]) -> String {
+    String::from_utf8_lossy(mem).trim_end_matches('\0').to_string()
+}
+
+impl BootSector {
+    /// Size of a boot sector in bytes
+    pub const SIZE: usize = 512;
+    /// Boot sector signature stored at offset 510
+    pub const SIGNATURE: u16 = 0xaa55;
+
+    /// Returns a boot sector
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - A byte slice representing the boot sector (Expected to be at least 512 Bytes)
+    pub fn new(mem: &[u8]) -> BootSector {
+        let fat32 = LittleEndian::read_u16(&mem[22..24]) == 0;
+        let ext = if fat32 { 64 } else { 36 };
+
+        BootSector {
+            jump: [mem[0], mem[1], mem[2]],
+            oem: label(&mem[3..11]),
+            bytes_per_sector: LittleEndian::read_u16(&mem[11..13]),
+            sectors_per_cluster: mem[13],
+            reserved_sectors: LittleEndian::read_u16(&mem[14..16]),
+            fat_count: mem[16],
+            root_entries: LittleEndian::read_u16(&mem[17..19]),
+            total_sectors_16: LittleEndian::read_u16(&mem[19..21]),
+            media: mem[21],
+            fat_size_16: LittleEndian::read_u16(&mem[22..24]),
+            sectors_per_track: LittleEndian::read_u16(&mem[24..26]),
+            heads: LittleEndian::read_u16(&mem[26..28]),
+            hidden_sectors: LittleEndian::read_u32(&mem[28..32]),
+            total_sectors_32: LittleEndian::read_u32(&mem[32..36]),
+            fat_size_32: if fat32 { LittleEndian::read_u32(&mem[36..40]) } else { 0 },
+            ext_flags: if fat32 { LittleEndian::read_u16(&mem[40..42]) } else { 0 },
+            fs_version: if fat32 { LittleEndian::read_u16(&mem[42..44]) } else { 0 },
+            root_cluster: if fat32 { LittleEndian::read_u32(&mem[44..48]) } else { 0 },
+            fs_info_sector: if fat32 { LittleEndian::read_u16(&mem[48..50]) } else { 0 },
+            backup_boot_sector: if fat32 { LittleEndian::read_u16(&mem[50..52]) } else { 0 },
+            drive_number: mem[ext],
+            ext_boot_signature: mem[ext + 2],
+            volume_id: LittleEndian::read_u32(&mem[ext + 3..ext + 7]),
+            volume_label: label(&mem[ext + 7..ext + 18]),
+            fs_type: label(&mem[ext + 18..ext + 26]),
+            signature: LittleEndian::read_u16(&mem[510..512]),
+        }
+    }
+
+    /// Checks if the boot sector describes a FAT32 volume
+    pub fn is_fat32(&self) -> bool {
+        self.fat_size_16 == 0
+    }
+
+    /// Returns the number of sectors per FAT table
+    pub fn fat_size(&self) -> u32 {
+        if self.is_fat32() { self.fat_size_32 } else { self.fat_size_16 as u32 }
+    }
+
+    /// Returns the total number of sectors of the volume
+    pub fn total_sectors(&self) -> u32 {
+        if self.total_sectors_16 == 0 { self.total_sectors_32 } else { self.total_sectors_16 as u32 }
+    }
+
+    /// Checks if the boot sector is usable to interpret the volume
+    ///
+    /// Only the fields required to locate the file system structures are
+    /// checked, i.e. the signature, the sector and cluster size, the size
+    /// of the reserved area and the FAT tables.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use greasy::formats::fat_boot::BootSector;
+    ///
+    /// let mut mem = [0u8; 512];
+    /// assert!(!BootSector::new(&mem).is_valid());
+    ///
+    /// mem[11] = 0x00; mem[12] = 0x02;     // 512 bytes per sector
+    /// mem[13] = 8;                        // 8 sectors per cluster
+    /// mem[14] = 32;                       // 32 reserved sectors
+    /// mem[16] = 2;                        // 2 FAT tables
+    /// mem[33] = 0x01;                     // 65536 sectors
+    /// mem[36] = 0x10;                     // 16 sectors per FAT (FAT32)
+    /// mem[510] = 0x55; mem[511] = 0xaa;
+    /// assert!(BootSector::new(&mem).is_valid());
+    /// ```
+    pub fn is_valid(&self) -> bool {
+        self.signature == BootSector::SIGNATURE &&
+            [512, 1024, 2048, 4096].contains(&self.bytes_per_sector) &&
+            self.sectors_per_cluster.is_power_of_two() &&
+            self.reserved_sectors != 0 &&
+            self.fat_count != 0 &&
+            self.fat_size() != 0 &&
+            self.total_sectors() != 0
+    }
+
+    /// Checks if mirroring of the FAT tables is disabled (FAT32 ExtFlags bit 7)
+    pub fn mirroring_disabled(&self) -> bool {
+        self.is_fat32() && (self.ext_flags & 0x80) != 0
+    }
+
+    /// Returns the number of the active FAT (FAT32 ExtFlags bits 0-3)
+    ///
+    /// The active FAT is only meaningful if mirroring is disabled.
+    pub fn active_fat(&self) -> u8 {
+        (self.ext_flags & 0x0f) as u8
+    }
+
+    /// Returns all fields of the boot sector as (name, value) pairs
+    ///
+    /// FAT32 specific fields are only included for FAT32 volumes.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let media = match self.media {
+            0xf0 => " (removable media)",
+            0xf8 => " (fixed disk)",
+            _ => "",
+        };
+
+        let mut fields = vec![
+            ("Jump Instruction", format!("{:02x} {:02x} {:02x}", self.jump[0], self.jump[1], self.jump[2])),
+            ("OEM Name", self.oem.clone()),
+            ("Bytes per Sector", self.bytes_per_sector.to_string()),
+            ("Sectors per Cluster", self.sectors_per_cluster.to_string()),
+            ("Reserved Sectors", self.reserved_sectors.to_string()),
+            ("Number of FATs", self.fat_count.to_string()),
+            ("Root Entries", self.root_entries.to_string()),
+            ("Total Sectors (16 bit)", self.total_sectors_16.to_string()),
+            ("Media Descriptor", format!("0x{:02x}{}", self.media, media)),
+            ("Sectors per FAT (16 bit)", self.fat_size_16.to_string()),
+            ("Sectors per Track", self.sectors_per_track.to_string()),
+            ("Number of Heads", self.heads.to_string()),
+            ("Hidden Sectors", self.hidden_sectors.to_string()),
+            ("Total Sectors (32 bit)", self.total_sectors_32.to_string()),
+        ];
+
+        if self.is_fat32() {
+            let ext_flags = if self.mirroring_disabled() {
+                format!("mirroring disabled, active FAT {}", self.active_fat())
+            } else {
+                "mirroring enabled".to_string()
+            };
+
+            fields.extend(vec![
+                ("Sectors per FAT (32 bit)", self.fat_size_32.to_string()),
+                ("Extended Flags", format!("0x{:04x} ({})", self.ext_flags, ext_flags)),
+                ("File System Version", format!("{}.{}", self.fs_version >> 8, self.fs_version & 0xff)),
+                ("Root Cluster", self.root_cluster.to_string()),
+                ("FSInfo Sector", self.fs_info_sector.to_string()),
+                ("Backup Boot Sector", self.backup_boot_sector.to_string()),
+            ]);
+        }
+
+        fields.extend(vec![
+            ("Drive Number", format!("0x{:02x}", self.drive_number)),
+            ("Extended Boot Signature", format!("0x{:02x}", self.ext_boot_signature)),
+            ("Volume ID", format!("0x{:08x}", self.volume_id)),
+            ("Volume Label", self.volume_label.clone()),
+            ("File System Type Label", self.fs_type.clone()),
+            ("Signature", format!("0x{:04x}", self.signature)),
+        ]);
+
+        fields
+    }
+
+    /// Returns a warning for every field that holds an invalid or unusual value
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use greasy::formats::fat_boot::BootSector;
+    ///
+    /// let mem = [0u8; 512];
+    /// let warnings = BootSector::new(&mem).warnings();
+    ///
+    /// assert!(warnings.iter().any(|w| w.contains("Signature")));
+    /// assert!(warnings.iter().any(|w| w.contains("Bytes per Sector")));
+    /// ```
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        if !(self.jump[0] == 0xeb && self.jump[2] == 0x90) && self.jump[0] != 0xe9 {
+            warnings.push("Jump Instruction: expected eb ?? 90 or e9 ?? ??".to_string());
+        }
+        if ![512, 1024, 2048, 4096].contains(&self.bytes_per_sector) {
+            warnings.push(format!("Bytes per Sector: {} is not one of 512, 1024, 2048, 4096", self.bytes_per_sector));
+        }
+        if !self.sectors_per_cluster.is_power_of_two() {
+            warnings.push(format!("Sectors per Cluster: {} is not a power of 2", self.sectors_per_cluster));
+        } else if self.sectors_per_cluster as u32 * self.bytes_per_sector as u32 > 32 * 1024 {
+            warnings.push("Sectors per Cluster: cluster size exceeds 32 KiB".to_string());
+        }
+        if self.reserved_sectors == 0 {
+            warnings.push("Reserved Sectors: must not be 0".to_string());
+        }
+        if self.fat_count == 0 {
+            warnings.push("Number of FATs: must not be 0".to_string());
+        } else if self.fat_count > 2 {
+            warnings.push(format!("Number of FATs: {} is unusual", self.fat_count));
+        }
+        if self.is_fat32() && self.root_entries != 0 {
+            warnings.push("Root Entries: must be 0 on FAT32".to_string());
+        }
+        if !self.is_fat32() && (self.root_entries as u32 * 32) % self.bytes_per_sector as u32 != 0 {
+            warnings.push("Root Entries: root directory doesn't fill whole sectors".to_string());
+        }
+        if (self.total_sectors_16 == 0) == (self.total_sectors_32 == 0) {
+            warnings.push("Total Sectors: exactly one of the 16 and 32 bit fields must be set".to_string());
+        }
+        if self.media != 0xf0 && self.media < 0xf8 {
+            warnings.push(format!("Media Descriptor: 0x{:02x} is not a valid media type", self.media));
+        }
+        if self.is_fat32() {
+            if self.fat_size_32 == 0 {
+                warnings.push("Sectors per FAT (32 bit): must not be 0".to_string());
+            }
+            if (self.ext_flags & 0xff70) != 0 {
+                warnings.push(format!("Extended Flags: reserved bits set (0x{:04x})", self.ext_flags));
+            }
+            if self.mirroring_disabled() && self.active_fat() >= self.fat_count {
+                warnings.push(format!("Extended Flags: active FAT {} doesn't exist", self.active_fat()));
+            }
+            if self.fs_version != 0 {
+                warnings.push(format!("File System Version: {}.{} is not supported",
+                                      self.fs_version >> 8, self.fs_version & 0xff));
+            }
+            if self.root_cluster < 2 {
+                warnings.push(format!("Root Cluster: {} is not a valid cluster", self.root_cluster));
+            }
+            if self.fs_info_sector == 0 || self.fs_info_sector >= self.reserved_sectors {
+                warnings.push(format!("FSInfo Sector: {} is outside of the reserved area", self.fs_info_sector));
+            }
+            if self.backup_boot_sector == 0 || self.backup_boot_sector >= self.reserved_sectors {
+                warnings.push(format!("Backup Boot Sector: {} is outside of the reserved area", self.backup_boot_sector));
+            } else if self.backup_boot_sector != 6 {
+                warnings.push(format!("Backup Boot Sector: {} is unusual (expected 6)", self.backup_boot_sector));
+            }
+        }
+        if self.ext_boot_signature != 0x29 && self.ext_boot_signature != 0x28 {
+            warnings.push(format!("Extended Boot Signature: 0x{:02x} is invalid, Volume ID and Label are not present",
+                                  self.ext_boot_signature));
+        } else if self.ext_boot_signature == 0x28 {
+            warnings.push("Extended Boot Signature: 0x28, Volume Label and File System Type are not present".to_string());
+        }
+        if self.signature != BootSector::SIGNATURE {
+            warnings.push(format!("Signature: 0x{:04x} (expected 0xaa55)", self.signature));
+        }
+
+        warnings
+    }
+
+    /// Compare the boot sector field by field with another one
+    ///
+    /// # Arguments
+    ///
+    /// * `backup` - The boot sector to compare with (usually the backup boot sector)
+    pub fn diff(&self, backup: &BootSector) -> Vec<FieldDiff> {
+        diff_fields(self.fields(), backup.fields())
+    }
+}
+
+impl FsInfo {
+    pub const LEAD_SIGNATURE: u32 = 0x4161_5252;
+    pub const STRUCT_SIGNATURE: u32 = 0x6141_7272;
+    pub const TRAIL_SIGNATURE: u32 = 0xaa55_0000;
+
+    /// Returns a FSInfo structure
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - A byte slice representing the FSInfo sector (Expected to be at least 512 Bytes)
+    pub fn new(mem: &[u8]) -> FsInfo {
+        FsInfo {
+            lead_signature: LittleEndian::read_u32(&mem[0..4]),
+            struct_signature: LittleEndian::read_u32(&mem[484..488]),
+            free_count: LittleEndian::read_u32(&mem[488..492]),
+            next_free: LittleEndian::read_u32(&mem[492..496]),
+            trail_signature: LittleEndian::read_u32(&mem[508..512]),
+        }
+    }
+
+    /// Checks if all three signatures are present
+    pub fn is_valid(&self) -> bool {
+        self.lead_signature == FsInfo::LEAD_SIGNATURE &&
+            self.struct_signature == FsInfo::STRUCT_SIGNATURE &&
+            self.trail_signature == FsInfo::TRAIL_SIGNATURE
+    }
+
+    /// Returns all fields of the FSInfo sector as (name, value) pairs
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("Lead Signature", format!("0x{:08x}", self.lead_signature)),
+            ("Struct Signature", format!("0x{:08x}", self.struct_signature)),
+            ("Free Cluster Count", self.free_count.to_string()),
+            ("Next Free Cluster", self.next_free.to_string()),
+            ("Trail Signature", format!("0x{:08x}", self.trail_signature)),
+        ]
+    }
+
+    /// Compare the FSInfo sector field by field with another one
+    ///
+    /// # Arguments
+    ///
+    /// * `backup` - The FSInfo sector to compare with (usually the backup FSInfo)
+    pub fn diff(&self, backup: &FsInfo) -> Vec<FieldDiff> {
+        diff_fields(self.fields(), backup.fields())
+    }
+}
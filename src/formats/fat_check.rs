@@ -0,0 +1,432 @@
+use super::fat::*;
+use super::fat_entry::*;
+use super::fat_table::*;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// Severity of a consistency check finding
+pub enum Severity {
+    /// Unusual, but not harmful
+    Info,
+    /// Wastes space or may lead to problems
+    Warning,
+    /// Data is or will be damaged
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The kind of problem a finding describes
+pub enum Problem {
+    /// A cluster is part of the chains of several entries
+    CrossLinked,
+    /// An allocated chain isn't referenced by any entry
+    LostChain,
+    /// The length of the chain disagrees with the size of the entry
+    SizeMismatch,
+    /// A directory doesn't start with valid `.` and `..` entries
+    InvalidDotEntries,
+    /// The start cluster of an entry lies outside of the cluster area
+    InvalidStartCluster,
+    /// A chain contains a bad cluster marker
+    BadCluster,
+    /// A chain ends in a free cluster or points to an invalid cluster
+    BrokenChain,
+    /// A chain loops back onto itself
+    ChainCycle,
+    /// The volume wasn't unmounted cleanly
+    Dirty,
+}
+
+#[derive(Debug, Clone)]
+/// A single problem found by the consistency check
+pub struct Finding {
+    /// Severity of the problem
+    pub severity: Severity,
+    /// The kind of problem
+    pub problem: Problem,
+    /// Path of the affected entry (if any)
+    pub path: Option<String>,
+    /// Clusters affected by the problem
+    pub clusters: Vec<u32>,
+    /// Human readable description
+    pub message: String,
+}
+
+/// The result of following a cluster chain through the FAT
+struct Chain {
+    /// All clusters of the chain
+    clusters: Vec<u32>,
+    /// The FAT entry that terminated the chain (None if it couldn't be read)
+    end: Option<TableEntry>,
+    /// Did the chain loop back onto itself? [yes/no]
+    cycle: bool,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "INFO"),
+            Severity::Warning => write!(f, "WARNING"),
+            Severity::Error => write!(f, "ERROR"),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Problem::CrossLinked => "cross-linked",
+            Problem::LostChain => "lost-chain",
+            Problem::SizeMismatch => "size-mismatch",
+            Problem::InvalidDotEntries => "invalid-dot-entries",
+            Problem::InvalidStartCluster => "invalid-start-cluster",
+            Problem::BadCluster => "bad-cluster",
+            Problem::BrokenChain => "broken-chain",
+            Problem::ChainCycle => "chain-cycle",
+            Problem::Dirty => "dirty",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Format a list of clusters as comma separated ranges
+///
+/// # Examples
+///
+/// ```
+/// use greasy::formats::fat_check::cluster_ranges;
+///
+/// assert_eq!("2-4,7,9-10", cluster_ranges(&[2, 3, 4, 7, 9, 10]));
+/// assert_eq!("", cluster_ranges(&[]));
+/// ```
+pub fn cluster_ranges(clusters: &[u32]) -> String {
+    cluster_runs(clusters).iter()
+          .map(|(a, b)| if a == b { a.to_string() } else { format!("{}-{}", a, b) })
+          .collect::<Vec<String>>()
+          .join(",")
+}
+
+impl fmt::Display for Finding {
+    /// Formats the finding as a single tab separated line:
+    /// severity, problem, path, clusters and message
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}\t{}\t{}\t{}\t{}", self.severity, self.problem,
+               self.path.as_deref().unwrap_or("-"), cluster_ranges(&self.clusters), self.message)
+    }
+}
+
+impl Finding {
+    fn new(severity: Severity, problem: Problem, path: Option<&str>, clusters: Vec<u32>, message: String) -> Finding {
+        Finding { severity, problem, path: path.map(|p| p.to_string()), clusters, message }
+    }
+}
+
+/// Follow a cluster chain through the active FAT and remember why it ended
+fn follow(fat: &Fat, start: u32) -> Chain {
+    let mut clusters = Vec::new();
+    let mut seen = HashSet::new();
+    let mut n = start;
+
+    loop {
+        if !seen.insert(n) {
+            return Chain { clusters, end: Some(TableEntry::Next(n)), cycle: true };
+        }
+        clusters.push(n);
+
+        match fat.read_table_entry(fat.active_fat(), &Cluster(n)).map(|v| TableEntry::new(v, fat.fat_table_entry_size())) {
+            Some(TableEntry::Next(next)) if fat.is_valid_cluster(next) => n = next,
+            end => return Chain { clusters, end, cycle: false },
+        }
+    }
+}
+
+/// Check the consistency of the whole volume
+///
+/// Deleted entries and entries within deleted directories are ignored. All chains
+/// are followed through the active FAT.
+///
+/// # Arguments
+///
+/// * `fat` - The file system to check
+/// * `files` - All (path, entry) pairs of the volume
+pub fn check(fat: &Fat, files: &[(String, Entry)]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut owners: HashMap<u32, Vec<&str>> = HashMap::new();
+    let cluster_size = fat.cluster_size() as u64;
+
+    for c in fat.root_clusters() {
+        owners.entry(c).or_default().push("/");
+    }
+
+    if fat.is_dirty() {
+        findings.push(Finding::new(Severity::Warning, Problem::Dirty, None, Vec::new(),
+                                   "volume wasn't unmounted cleanly (dirty bit set)".to_string()));
+    }
+
+    let deleted_dirs: Vec<String> = files.iter()
+                                         .filter(|(_, e)| e.is_deleted() && e.is_subdir_entry())
+                                         .map(|(p, _)| format!("{}/", p))
+                                         .collect();
+
+    for (path, e) in files {
+        if e.is_deleted() || e.is_disk_volume_entry() || deleted_dirs.iter().any(|d| path.starts_with(d)) {
+            continue;
+        }
+
+        let start = e.start().0;
+        if start == 0 {
+            if !e.is_subdir_entry() && e.size() != 0 {
+                findings.push(Finding::new(Severity::Error, Problem::InvalidStartCluster, Some(path), Vec::new(),
+                                           format!("file has a size of {} bytes but no start cluster", e.size())));
+            }
+            continue;
+        }
+
+        if !fat.is_valid_cluster(start) {
+            findings.push(Finding::new(Severity::Error, Problem::InvalidStartCluster, Some(path), vec![start],
+                                       format!("start cluster {} is outside of the cluster area", start)));
+            continue;
+        }
+
+        let chain = follow(fat, start);
+        let last = *chain.clusters.last().unwrap_or(&start);
+
+        if chain.cycle {
+            findings.push(Finding::new(Severity::Error, Problem::ChainCycle, Some(path), vec![last],
+                                       format!("chain loops back from cluster {}", last)));
+        } else {
+            match chain.end {
+                Some(TableEntry::EndOfChain) => (),
+                Some(TableEntry::Bad) => findings.push(Finding::new(Severity::Error, Problem::BadCluster, Some(path), vec![last],
+                                                 format!("chain contains bad cluster {}", last))),
+                Some(TableEntry::Free) => findings.push(Finding::new(Severity::Error, Problem::BrokenChain, Some(path), vec![last],
+                                                  format!("chain ends in free cluster {}", last))),
+                Some(TableEntry::Next(n)) => findings.push(Finding::new(Severity::Error, Problem::BrokenChain, Some(path), vec![last],
+                                                     format!("cluster {} points to invalid cluster {}", last, n))),
+                Some(TableEntry::Reserved(v)) => findings.push(Finding::new(Severity::Error, Problem::BrokenChain, Some(path), vec![last],
+                                                         format!("cluster {} holds reserved value 0x{:x}", last, v))),
+                None => findings.push(Finding::new(Severity::Error, Problem::BrokenChain, Some(path), vec![last],
+                                      format!("FAT entry of cluster {} lies beyond the end of the image", last))),
+            }
+        }
+
+        if !e.is_subdir_entry() {
+            let expected = (e.size() as u64).div_ceil(cluster_size) as usize;
+            if expected != chain.clusters.len() {
+                findings.push(Finding::new(Severity::Error, Problem::SizeMismatch, Some(path), chain.clusters.clone(),
+                                           format!("chain has {} cluster(s) but the size of {} bytes requires {}",
+                                                   chain.clusters.len(), e.size(), expected)));
+            }
+        } else {
+            findings.extend(check_dot_entries(fat, files, path, e));
+        }
+
+        for c in chain.clusters {
+            owners.entry(c).or_default().push(path);
+        }
+    }
+
+    // cross-linked clusters, grouped by the set of entries sharing them
+    let mut shared: BTreeMap<Vec<&str>, Vec<u32>> = BTreeMap::new();
+    for (c, paths) in &owners {
+        if paths.len() > 1 {
+            shared.entry(paths.clone()).or_default().push(*c);
+        }
+    }
+    for (paths, mut clusters) in shared {
+        clusters.sort_unstable();
+        for path in &paths {
+            let others: Vec<&str> = paths.iter().filter(|p| *p != path).cloned().collect();
+            findings.push(Finding::new(Severity::Error, Problem::CrossLinked, Some(path), clusters.clone(),
+                                       format!("{} cluster(s) shared with {}", clusters.len(), others.join(", "))));
+        }
+    }
+
+    findings.extend(lost_chains(fat, &owners));
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+
+    findings
+}
+
+/// Check that the directory starts with a `.` entry pointing to itself and a
+/// `..` entry pointing to its parent (0 for the root directory)
+fn check_dot_entries(fat: &Fat, files: &[(String, Entry)], path: &str, dir: &Entry) -> Option<Finding> {
+    let entries = fat.dir_entries(dir);
+    let parent_path = &path[..path.rfind('/').unwrap_or(0)];
+    let parent = match files.iter().find(|(p, e)| p == parent_path && !e.is_deleted()) {
+        Some((_, e)) => e.start().0,
+        None => 0,
+    };
+    // '..' entries of top level directories point to cluster 0, some
+    // implementations use the root cluster on FAT32 instead
+    let parent_ok = |c: u32| c == parent || (parent == 0 && fat.root_clusters().first() == Some(&c));
+
+    let message = match (entries.first(), entries.get(1)) {
+        (Some(dot), Some(dotdot)) if dot.is_this_entry() && dotdot.is_prev_entry() => {
+            if dot.start().0 != dir.start().0 {
+                format!("'.' points to cluster {} instead of {}", dot.start().0, dir.start().0)
+            } else if !parent_ok(dotdot.start().0) {
+                format!("'..' points to cluster {} instead of {}", dotdot.start().0, parent)
+            } else {
+                return None;
+            }
+        },
+        _ => "directory doesn't start with '.' and '..' entries".to_string(),
+    };
+
+    Some(Finding::new(Severity::Error, Problem::InvalidDotEntries, Some(path), vec![dir.start().0], message))
+}
+
+/// Find allocated chains that aren't referenced by any entry
+///
+/// Every allocated cluster that isn't owned by an entry and isn't the successor
+/// of another unowned cluster is the head of a lost chain.
+fn lost_chains(fat: &Fat, owners: &HashMap<u32, Vec<&str>>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut unowned = Vec::new();
+    let mut successors = HashSet::new();
+
+    for c in 2..=fat.total_clusters() {
+        if owners.contains_key(&c) {
+            continue;
+        }
+
+        match fat.read_table_entry(fat.active_fat(), &Cluster(c)).map(|v| TableEntry::new(v, fat.fat_table_entry_size())) {
+            None | Some(TableEntry::Free) | Some(TableEntry::Bad) => (),
+            Some(TableEntry::Next(n)) => {
+                successors.insert(n);
+                unowned.push(c);
+            },
+            Some(_) => unowned.push(c),
+        }
+    }
+
+    let mut claimed = HashSet::new();
+    let heads: Vec<u32> = unowned.iter().filter(|c| !successors.contains(c)).cloned().collect();
+
+    for head in heads {
+        let chain: Vec<u32> = follow(fat, head).clusters.into_iter()
+                                               .take_while(|c| !owners.contains_key(c) && claimed.insert(*c))
+                                               .collect();
+        findings.push(Finding::new(Severity::Warning, Problem::LostChain, None, chain.clone(),
+                                   format!("lost chain of {} cluster(s) starting at cluster {}", chain.len(), head)));
+    }
+
+    // chains without a head are cycles of lost clusters
+    for c in unowned {
+        if !claimed.contains(&c) {
+            let chain: Vec<u32> = follow(fat, c).clusters.into_iter()
+                                                 .take_while(|x| !owners.contains_key(x) && claimed.insert(*x))
+                                                 .collect();
+            findings.push(Finding::new(Severity::Warning, Problem::LostChain, None, chain.clone(),
+                                       format!("lost cyclic chain of {} cluster(s) containing cluster {}", chain.len(), c)));
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fat_test::*;
+
+    /// Write a directory cluster with `.` and `..` followed by the given entries
+    fn dir(image: &mut Image, cluster: u32, parent: u32, entries: &[[u8; 32]]) {
+        image.set_chain(&[cluster]);
+        image.set_dir(cluster, 0, &[short_entry(b".          ", 0x10, cluster, 0), short_entry(b"..         ", 0x10, parent, 0)]);
+        image.set_dir(cluster, 2, entries);
+    }
+
+    fn problems(findings: &[Finding]) -> Vec<(Problem, Option<&str>, &[u32])> {
+        findings.iter().map(|f| (f.problem, f.path.as_deref(), &f.clusters[..])).collect()
+    }
+
+    #[test]
+    fn deleted_directory_doesnt_hide_live_directory() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"X          ", 0x10, 3, 0), short_entry(b"B          ", 0x10, 5, 0)]);
+        // the deleted directory /X/_A started at the cluster now used by /B
+        dir(&mut image, 3, 0, &[short_entry(b"\xe5A         ", 0x10, 5, 0)]);
+        dir(&mut image, 5, 0, &[short_entry(b"FILE    TXT", 0x20, 6, 100)]);
+        image.set_chain(&[6]);
+
+        assert!(image.open().check().is_empty());
+    }
+
+    #[test]
+    fn finds_cross_linked_clusters() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"A       TXT", 0x20, 2, 2048), short_entry(b"B       TXT", 0x20, 3, 1024)]);
+        image.set_chain(&[2, 3]);
+
+        let findings = image.open().check();
+
+        assert_eq!(problems(&findings), vec![
+            (Problem::CrossLinked, Some("/A.TXT"), &[3][..]),
+            (Problem::CrossLinked, Some("/B.TXT"), &[3][..]),
+        ]);
+        assert_eq!(findings[0].message, "1 cluster(s) shared with /B.TXT");
+    }
+
+    #[test]
+    fn finds_lost_chains() {
+        let mut image = Image::new();
+        image.set_chain(&[5, 6]);
+        image.set_chain(&[9]);
+
+        let findings = image.open().check();
+
+        assert_eq!(problems(&findings), vec![(Problem::LostChain, None, &[5, 6][..]), (Problem::LostChain, None, &[9][..])]);
+        assert!(findings.iter().all(|f| f.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn finds_chain_cycles() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"LOOP    TXT", 0x20, 2, 2048)]);
+        image.set_fat(2, 3);
+        image.set_fat(3, 2);
+
+        let findings = image.open().check();
+
+        assert_eq!(problems(&findings), vec![(Problem::ChainCycle, Some("/LOOP.TXT"), &[3][..])]);
+        assert_eq!(findings[0].message, "chain loops back from cluster 3");
+    }
+
+    #[test]
+    fn finds_size_mismatches() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"SHORT   TXT", 0x20, 2, 5000), short_entry(b"LONG    TXT", 0x20, 4, 10)]);
+        image.set_chain(&[2]);
+        image.set_chain(&[4, 5]);
+
+        let findings = image.open().check();
+
+        assert_eq!(problems(&findings), vec![
+            (Problem::SizeMismatch, Some("/SHORT.TXT"), &[2][..]),
+            (Problem::SizeMismatch, Some("/LONG.TXT"), &[4, 5][..]),
+        ]);
+        assert_eq!(findings[0].message, "chain has 1 cluster(s) but the size of 5000 bytes requires 5");
+    }
+
+    #[test]
+    fn follows_chains_through_the_active_fat() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"FILE    TXT", 0x20, 2, 2048)]);
+        image.set_chain(&[2, 3]);
+        // FAT 1 has lost the end of the chain
+        image.set_fat_in(1, 3, 0);
+
+        let mut fat = image.open();
+        assert!(fat.check().is_empty());
+
+        fat.select_fat(1).unwrap();
+        let findings = fat.check();
+        assert_eq!(problems(&findings), vec![(Problem::BrokenChain, Some("/FILE.TXT"), &[3][..])]);
+        assert_eq!(findings[0].message, "chain ends in free cluster 3");
+    }
+}
@@ -0,0 +1,233 @@
+use super::fat::*;
+use super::fat_entry::*;
+use super::fat_hash::Hashes;
+use super::fat_recover;
+use super::fat_reader::FileReader;
+use super::fat_table::cluster_runs;
+use super::fat_walk::{DeletedDirs, WalkOptions};
+use std::{
+    cmp,
+    io::{self, Write},
+};
+
+/// Escape the characters that can't appear literally in XML text and attributes
+///
+/// # Examples
+///
+/// ```
+/// use greasy::formats::fat_dfxml::escape;
+///
+/// assert_eq!("a &lt;b&gt; &amp; &quot;c&quot;", escape("a <b> & \"c\""));
+/// ```
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c if (c as u32) < 0x20 && c != '\t' && c != '\n' && c != '\r' => escaped.push('\u{fffd}'),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Returns the byte runs of an entry as (file offset, volume offset, length)
+///
+/// Runs of consecutive clusters are merged. The runs of a file end with the
+/// file size, the runs of a directory cover all of its clusters.
+///
+/// The chain of deleted and unlinked entries is gone, so their presumed
+/// clusters are used instead. No runs are returned if these can't be recovered.
+///
+/// # Arguments
+///
+/// * `fat` - The file system the entry belongs to
+/// * `e` - The directory entry
+/// * `deleted` - Is the entry or one of its ancestors deleted? (see `DeletedDirs`)
+pub fn byte_runs(fat: &Fat, e: &Entry, deleted: bool) -> Vec<(u64, usize, u64)> {
+    let clusters: Vec<u32> = if deleted || fat_recover::is_unlinked(fat, e) {
+        match fat_recover::recover(fat, e) {
+            Some(recovered) => recovered.clusters().iter().flatten().map(|c| c.0).collect(),
+            None => return Vec::new(),
+        }
+    } else {
+        e.clusters().iter().flatten().map(|c| c.0).collect()
+    };
+    let cluster_size = fat.cluster_size() as u64;
+    let mut remaining = if e.is_subdir_entry() { u64::MAX } else { e.size() as u64 };
+    let mut file_offset = 0;
+    let mut runs = Vec::new();
+
+    for (first, last) in cluster_runs(&clusters) {
+        if remaining == 0 {
+            break;
+        }
+
+        let len = cmp::min((last - first + 1) as u64 * cluster_size, remaining);
+        runs.push((file_offset, fat.clusters_to_offsets(&[Cluster(first)])[0], len));
+        file_offset += len;
+        remaining -= len;
+    }
+
+    runs
+}
+
+/// Write a Digital Forensics XML (DFXML) report of the volume
+///
+/// The report contains a `<volume>` element with the geometry of the file
+/// system and a `<fileobject>` for every entry.
+///
+/// # Arguments
+///
+/// * `w` - Destination of the report
+/// * `fat` - The file system
+/// * `image` - File name of the image (reported as source)
+/// * `hash` - Add the MD5, SHA-1 and SHA-256 of every file that isn't deleted
+///   (nor within a deleted directory)
+pub fn write<W: Write>(w: &mut W, fat: &dyn FAT, image: &str, hash: bool) -> io::Result<()> {
+    let base = fat.fat();
+
+    writeln!(w, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(w, "<dfxml xmlns=\"http://www.forensicswiki.org/wiki/Category:Digital_Forensics_XML\" \
+                 xmlns:dc=\"http://purl.org/dc/elements/1.1/\" version=\"1.0\">")?;
+    writeln!(w, "  <metadata>\n    <dc:type>File System Metadata</dc:type>\n  </metadata>")?;
+    writeln!(w, "  <creator>\n    <program>greasy</program>\n    <version>{}</version>\n  </creator>", env!("CARGO_PKG_VERSION"))?;
+    writeln!(w, "  <source>\n    <image_filename>{}</image_filename>\n  </source>", escape(image))?;
+
+    writeln!(w, "  <volume offset=\"0\">")?;
+    writeln!(w, "    <partition_offset>0</partition_offset>")?;
+    writeln!(w, "    <sector_size>{}</sector_size>", base.bytes_per_sector())?;
+    writeln!(w, "    <block_size>{}</block_size>", base.cluster_size())?;
+    writeln!(w, "    <ftype_str>{}</ftype_str>", base.fat_type().to_lowercase())?;
+    writeln!(w, "    <block_count>{}</block_count>", base.total_clusters() - 1)?;
+    writeln!(w, "    <first_block>2</first_block>")?;
+    writeln!(w, "    <last_block>{}</last_block>", base.total_clusters())?;
+    writeln!(w, "    <allocated_only>0</allocated_only>")?;
+
+    let mut deleted = DeletedDirs::default();
+    for item in fat.walk(WalkOptions::default()) {
+        write_fileobject(w, base, &item.path, &item.entry, deleted.is_deleted(&item), hash)?;
+    }
+
+    writeln!(w, "  </volume>")?;
+    writeln!(w, "</dfxml>")
+}
+
+/// Write the `<fileobject>` element of a single entry
+///
+/// Entries of deleted directories aren't allocated, even if they aren't marked
+/// as deleted themselves.
+fn write_fileobject<W: Write>(w: &mut W, fat: &Fat, path: &str, e: &Entry, deleted: bool, hash: bool) -> io::Result<()> {
+    let name_type = if e.is_disk_volume_entry() {
+        "v"
+    } else if e.is_subdir_entry() {
+        "d"
+    } else {
+        "r"
+    };
+
+    writeln!(w, "    <fileobject>")?;
+    writeln!(w, "      <filename>{}</filename>", escape(path.trim_start_matches('/')))?;
+    writeln!(w, "      <filesize>{}</filesize>", e.size())?;
+    writeln!(w, "      <alloc>{}</alloc>", if deleted { 0 } else { 1 })?;
+    writeln!(w, "      <name_type>{}</name_type>", name_type)?;
+    writeln!(w, "      <meta_type>{}</meta_type>", if name_type == "d" { 2 } else { 1 })?;
+
+    for (tag, time) in [("mtime", e.written()), ("atime", e.accessed()), ("crtime", e.created())] {
+        if let Some(t) = time {
+            writeln!(w, "      <{}>{}</{}>", tag, t.to_iso8601(), tag)?;
+        }
+    }
+
+    let runs = byte_runs(fat, e, deleted);
+    if !runs.is_empty() {
+        writeln!(w, "      <byte_runs>")?;
+        for (file_offset, offset, len) in runs {
+            writeln!(w, "        <byte_run file_offset=\"{}\" fs_offset=\"{}\" img_offset=\"{}\" len=\"{}\"/>",
+                     file_offset, offset, offset, len)?;
+        }
+        writeln!(w, "      </byte_runs>")?;
+    }
+
+    // files whose clusters lie outside of the image can't be hashed
+    let hashes = if hash && name_type == "r" && !deleted {
+        Hashes::compute(&mut FileReader::new(fat, e)).ok()
+    } else {
+        None
+    };
+
+    if let Some(h) = hashes {
+        writeln!(w, "      <hashdigest type=\"md5\">{}</hashdigest>", h.md5)?;
+        writeln!(w, "      <hashdigest type=\"sha1\">{}</hashdigest>", h.sha1)?;
+        writeln!(w, "      <hashdigest type=\"sha256\">{}</hashdigest>", h.sha256)?;
+    }
+
+    writeln!(w, "    </fileobject>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fat_test::*;
+
+    fn runs(image: &Image) -> Vec<(u64, usize, u64)> {
+        let fat = image.open();
+        let e = fat.root_dir().next().unwrap();
+        byte_runs(fat.fat(), &e, e.is_deleted())
+    }
+
+    fn report(image: &Image) -> String {
+        let mut out = Vec::new();
+        write(&mut out, &*image.open(), "test.dd", true).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn deleted_file_uses_presumed_clusters() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"\xe5ELETED TXT", 0x20, 3, 1500)]);
+
+        assert_eq!(runs(&image), vec![(0, Image::cluster_offset(3), 1500)]);
+    }
+
+    #[test]
+    fn deleted_file_with_reallocated_start_cluster_has_no_runs() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"\xe5ELETED TXT", 0x20, 3, 1500)]);
+        image.set_chain(&[3, 4]);
+
+        assert!(runs(&image).is_empty());
+    }
+
+    #[test]
+    fn files_of_deleted_directories_are_unallocated() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"\xe5IR        ", 0x10, 3, 0), short_entry(b"OTHER   TXT", 0x20, 4, 3)]);
+        image.set_dir(3, 0, &[
+            short_entry(b".          ", 0x10, 3, 0),
+            short_entry(b"..         ", 0x10, 0, 0),
+            short_entry(b"FILE    TXT", 0x20, 4, 3),
+        ]);
+        // the cluster of FILE.TXT has been reused by OTHER.TXT
+        image.set_chain(&[4]);
+        image.write(Image::cluster_offset(4), b"abc");
+
+        let report = report(&image);
+        let object = |name: &str| {
+            let start = report.find(&format!("<filename>{}</filename>", name)).unwrap();
+            report[start..start + report[start..].find("</fileobject>").unwrap()].to_string()
+        };
+
+        assert!(object("_IR/FILE.TXT").contains("<alloc>0</alloc>"));
+        assert!(!object("_IR/FILE.TXT").contains("<byte_runs>"));
+        assert!(!object("_IR/FILE.TXT").contains("<hashdigest"));
+        assert!(object("OTHER.TXT").contains("<alloc>1</alloc>"));
+        assert!(object("OTHER.TXT").contains("<hashdigest type=\"md5\">900150983cd24fb0d6963f7d28e17f72</hashdigest>"));
+    }
+}
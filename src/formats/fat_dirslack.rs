@@ -0,0 +1,164 @@
+use super::fat::*;
+use super::fat_entry::*;
+use super::fat_walk::WalkOptions;
+use byteorder::{ByteOrder, LittleEndian};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+/// Characters that aren't allowed within a short name
+const INVALID_SHORT_NAME_CHARS: &[u8] = b"\"*+,./:;<=>?[\\]|";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Classification of an entry found after the end of directory marker
+pub enum Kind {
+    /// Looks like a regular entry (no deletion marker), e.g. hidden on purpose
+    Hidden,
+    /// Carries the deletion marker, e.g. left behind by a directory that shrank
+    Residual,
+}
+
+#[derive(Debug, Clone)]
+/// A plausible directory entry found in the slack of a directory
+pub struct SlackEntry {
+    pub kind: Kind,
+    /// Path of the directory whose slack holds the entry (`/` for the root directory)
+    pub dir: String,
+    /// The entry (including its long name, if the LFN entries survived)
+    pub entry: Entry,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kind::Hidden => write!(f, "hidden"),
+            Kind::Residual => write!(f, "residual"),
+        }
+    }
+}
+
+/// Checks if 32 bytes look like a valid short directory entry or LFN entry
+///
+/// Random data and zeroed slots are rejected by checking the name
+/// characters, the attribute bits, the start cluster, the size and the
+/// date of the last write.
+///
+/// # Arguments
+///
+/// * `fat` - The file system the entry would belong to
+/// * `raw` - The 32 bytes of the slot
+pub fn is_plausible(fat: &Fat, raw: &[u8]) -> bool {
+    if raw.len() < 32 || raw[0] == 0x00 {
+        return false;
+    }
+
+    if LFNEntry::is_lfn_entry(raw[11]) {
+        let seq = raw[0];
+        let valid_seq = seq == 0xe5 || (seq & 0xa0 == 0 && (1..=20).contains(&(seq & 0x1f)));
+        return valid_seq && raw[12] == 0 && raw[26] == 0 && raw[27] == 0;
+    }
+
+    let valid_name = match &raw[..11] {
+        b".          " | b"..         " => true,
+        name => name[0] != b' ' && name.iter().enumerate().all(|(i, c)| {
+            (i == 0 && (*c == 0xe5 || *c == 0x05)) || *c >= 0x80
+                || (*c >= 0x20 && !c.is_ascii_lowercase() && !INVALID_SHORT_NAME_CHARS.contains(c))
+        }),
+    };
+
+    let attributes = raw[11];
+    let start = ((LittleEndian::read_u16(&raw[20..22]) as u32) << 16) + LittleEndian::read_u16(&raw[26..28]) as u32;
+    let size = LittleEndian::read_u32(&raw[28..32]) as usize;
+    let written_day = LittleEndian::read_u16(&raw[24..26]);
+    let written_hms = LittleEndian::read_u16(&raw[22..24]);
+
+    valid_name
+        && attributes & 0xc0 == 0
+        && (start == 0 || fat.is_valid_cluster(start))
+        && size <= fat.mem().len()
+        && (attributes & 0x10 == 0 || size == 0)
+        && (written_day == 0 || Timestamp::new(written_day, written_hms, 0).is_some())
+}
+
+/// Parse all plausible entries stored after the end of directory marker
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+/// * `dir` - Path of the directory
+/// * `offsets` - Byte offsets of the clusters of the directory
+/// * `max` - Number of bytes per cluster
+fn scan(fat: &Fat, dir: &str, offsets: &[usize], max: usize) -> Vec<SlackEntry> {
+    let mem = fat.mem();
+    let slots: Vec<usize> = offsets.iter()
+        .flat_map(|o| (*o..o + max).step_by(32))
+        .filter(|a| a + 32 <= mem.len())
+        .collect();
+
+    let marker = match slots.iter().position(|a| mem[*a] == 0x00) {
+        Some(marker) => marker,
+        None => return Vec::new(),
+    };
+
+    let mut lfns: HashMap<u8, Vec<LFNEntry>> = HashMap::new();
+    let mut entries = Vec::new();
+
+    for a in &slots[marker + 1..] {
+        let raw = &mem[*a..a + 32];
+        if !is_plausible(fat, raw) {
+            continue;
+        }
+
+        if LFNEntry::is_lfn_entry(raw[11]) {
+            let lfn = LFNEntry::new(raw);
+            lfns.entry(lfn.checksum()).or_default().push(lfn);
+        } else {
+            let mut entry = Entry::new(raw);
+            entry.add_address(*a);
+            entries.push(entry);
+        }
+    }
+
+    entries.into_iter()
+        .map(|mut entry| {
+            entry.restore_name(&lfns, fat.placeholder());
+            entry.add_lfn(&mut lfns);
+            entry.add_clusters(fat.get_cluster_chain_in(fat.active_fat(), entry.start()));
+            let kind = if entry.is_deleted() { Kind::Residual } else { Kind::Hidden };
+            SlackEntry { kind, dir: dir.to_string(), entry }
+        })
+        .collect()
+}
+
+/// Returns the entries hidden in the slack of every directory
+///
+/// The slack of a directory starts right after the first slot whose first
+/// byte is 0x00 (end of directory marker) and ends with the last cluster of
+/// the directory (or the end of the FAT16 root directory). Normal directory
+/// parsing stops at the marker, so any entry found there is invisible to the
+/// operating system.
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+pub fn dir_slack(fat: &dyn FAT) -> Vec<SlackEntry> {
+    let base = fat.fat();
+    let (root, max) = fat.root_area();
+    let mut entries = scan(base, "/", &root, max);
+    // several entries can point to the same directory
+    let mut scanned = HashSet::new();
+
+    for item in fat.walk(WalkOptions::default()) {
+        let e = &item.entry;
+        if !e.is_subdir_entry() || e.is_disk_volume_entry() || !scanned.insert(e.start().0) {
+            continue;
+        }
+
+        if let Some(clusters) = e.clusters() {
+            entries.extend(scan(base, &item.path, &base.clusters_to_offsets(clusters), base.cluster_size()));
+        }
+    }
+
+    entries
+}
@@ -0,0 +1,209 @@
+use super::fat::*;
+use super::fat_recover;
+use super::fat_table::{cluster_runs, TableEntry};
+use serde::Serialize;
+use std::{
+    cmp,
+    fmt::Write as _,
+    io::{self, Write},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// Maps a run of consecutive clusters of an export back onto the volume
+pub struct RunMapping {
+    /// Offset of the run within the exported file
+    pub output_offset: u64,
+    /// Offset of the run within the volume
+    pub volume_offset: usize,
+    pub first_cluster: u32,
+    pub last_cluster: u32,
+    /// Length of the run (in bytes)
+    pub length: u64,
+}
+
+/// Render bytes as canonical hexdump (16 bytes per line with offset and ASCII column)
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to render
+/// * `offset` - Offset of the first byte (e.g. within the volume)
+///
+/// # Examples
+///
+/// ```
+/// use greasy::formats::fat_dump::hexdump;
+///
+/// assert_eq!("00000010  41 42 00                                          |AB.|\n", hexdump(b"AB\0", 0x10));
+/// ```
+pub fn hexdump(bytes: &[u8], offset: usize) -> String {
+    let mut s = String::new();
+
+    for (i, line) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = (0..16)
+            .map(|j| line.get(j).map(|b| format!("{:02x}", b)).unwrap_or_else(|| "  ".to_string()))
+            .collect();
+        let ascii: String = line.iter()
+            .map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' })
+            .collect();
+
+        let _ = writeln!(s, "{:08x}  {}  {}  |{}|", offset + i * 16, hex[..8].join(" "), hex[8..].join(" "), ascii);
+    }
+
+    s
+}
+
+/// Returns the bytes from `start` up to `end` (exclusive), cut off at the end of the image
+fn bytes(fat: &Fat, start: usize, end: usize) -> io::Result<&[u8]> {
+    let mem = fat.mem();
+
+    if start >= mem.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("offset 0x{:x} lies beyond the end of the image", start)));
+    }
+
+    Ok(&mem[start..cmp::min(end, mem.len())])
+}
+
+/// Write the content of clusters as raw bytes or as an annotated hexdump
+///
+/// In hexdump mode every cluster is preceded by a line with its sector range
+/// and its entry in the active FAT, and the offsets are relative to the volume.
+///
+/// # Arguments
+///
+/// * `w` - Destination of the dump
+/// * `fat` - The file system
+/// * `clusters` - The clusters to dump (in this order)
+/// * `hex` - Render a hexdump instead of the raw bytes
+pub fn dump_clusters<W: Write>(w: &mut W, fat: &Fat, clusters: &[u32], hex: bool) -> io::Result<()> {
+    for c in clusters {
+        if !fat.is_valid_cluster(*c) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} isn't a valid cluster", c)));
+        }
+
+        let sector = fat.cluster_to_sector(&Cluster(*c));
+        let start = fat.offset(&sector);
+        let data = bytes(fat, start, start + fat.cluster_size())?;
+
+        if hex {
+            let entry = match fat.read_table_entry(fat.active_fat(), &Cluster(*c)) {
+                Some(v) => TableEntry::new(v, fat.fat_table_entry_size()).to_string(),
+                None => "unreadable".to_string(),
+            };
+            writeln!(w, "# cluster {} (sectors {} - {}, FAT entry: {})",
+                     c, sector.0, sector.0 + fat.sectors_per_cluster() as u32 - 1, entry)?;
+            write!(w, "{}", hexdump(data, start))?;
+        } else {
+            w.write_all(data)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write a range of sectors as raw bytes or as a hexdump
+///
+/// # Arguments
+///
+/// * `w` - Destination of the dump
+/// * `fat` - The file system
+/// * `first` - First sector of the range
+/// * `last` - Last sector of the range (inclusive, must lie within the volume)
+/// * `hex` - Render a hexdump instead of the raw bytes
+pub fn dump_sectors<W: Write>(w: &mut W, fat: &Fat, first: u32, last: u32, hex: bool) -> io::Result<()> {
+    if first > last || last >= fat.total_sectors() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("sectors {} - {} don't lie within the {} sectors of the volume", first, last, fat.total_sectors())));
+    }
+
+    let start = fat.offset(&Sector(first));
+    let data = bytes(fat, start, fat.offset(&Sector(last + 1)))?;
+
+    if hex {
+        writeln!(w, "# sectors {} - {}", first, last)?;
+        write!(w, "{}", hexdump(data, start))
+    } else {
+        w.write_all(data)
+    }
+}
+
+/// Write all clusters marked free in the active FAT as one contiguous stream
+///
+/// Returns the mapping of every run of consecutive free clusters from the
+/// output back onto the volume. Clusters beyond the end of the image are
+/// skipped.
+///
+/// # Arguments
+///
+/// * `w` - Destination of the unallocated space
+/// * `fat` - The file system
+pub fn export_unallocated<W: Write>(w: &mut W, fat: &Fat) -> io::Result<Vec<RunMapping>> {
+    let free: Vec<u32> = (2..=fat.total_clusters()).filter(|c| fat_recover::is_free(fat, *c)).collect();
+    let mut mapping = Vec::new();
+    let mut output_offset = 0;
+
+    for (first, last) in cluster_runs(&free) {
+        let start = fat.clusters_to_offsets(&[Cluster(first)])[0];
+        let end = cmp::min(start + (last - first + 1) as usize * fat.cluster_size(), fat.mem().len());
+        if start >= end {
+            break;
+        }
+
+        w.write_all(&fat.mem()[start..end])?;
+
+        let length = (end - start) as u64;
+        let last = first + ((end - start).div_ceil(fat.cluster_size()) as u32) - 1;
+        mapping.push(RunMapping { output_offset, volume_offset: start, first_cluster: first, last_cluster: last, length });
+        output_offset += length;
+    }
+
+    Ok(mapping)
+}
+
+/// Map an offset within an export of the unallocated space back onto the volume
+///
+/// Returns the cluster and the offset within the volume.
+///
+/// # Arguments
+///
+/// * `mapping` - The mapping returned by `export_unallocated()`
+/// * `offset` - Offset within the exported file
+///
+/// # Examples
+///
+/// ```
+/// use greasy::formats::fat_dump::{source_offset, RunMapping};
+///
+/// let mapping = vec![
+///     RunMapping { output_offset: 0, volume_offset: 0x9000, first_cluster: 2, last_cluster: 3, length: 2048 },
+///     RunMapping { output_offset: 2048, volume_offset: 0xb000, first_cluster: 10, last_cluster: 10, length: 1024 },
+/// ];
+///
+/// assert_eq!(Some((3, 0x9410)), source_offset(&mapping, 1024, 0x410));
+/// assert_eq!(Some((10, 0xb001)), source_offset(&mapping, 1024, 2049));
+/// assert_eq!(None, source_offset(&mapping, 1024, 3072));
+/// ```
+pub fn source_offset(mapping: &[RunMapping], cluster_size: usize, offset: u64) -> Option<(u32, usize)> {
+    let run = mapping.iter().find(|r| offset >= r.output_offset && offset < r.output_offset + r.length)?;
+    let within = (offset - run.output_offset) as usize;
+
+    Some((run.first_cluster + (within / cluster_size) as u32, run.volume_offset + within))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fat_test::*;
+
+    #[test]
+    fn dump_sectors_stays_within_the_volume() {
+        let fat = Image::new().open();
+        let last = fat.fat().total_sectors() - 1;
+        let mut out = Vec::new();
+
+        dump_sectors(&mut out, fat.fat(), last, last, false).unwrap();
+        assert_eq!(out.len(), BYTES_PER_SECTOR);
+
+        assert!(dump_sectors(&mut io::sink(), fat.fat(), last, last + 1, false).is_err());
+        assert!(dump_sectors(&mut io::sink(), fat.fat(), 0, u32::MAX, false).is_err());
+    }
+}
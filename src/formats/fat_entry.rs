@@ -1,11 +1,12 @@
 use super::fat::*;
 use std::{
-    ffi::CString,
     collections::HashMap,
+    fmt,
 };
 use byteorder::{ByteOrder, LittleEndian};
+use serde::{Serialize, Serializer};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// Entry represents an FAT directory entry
 ///
 /// A directory entry can belong to a file or to a subdirectory.
@@ -41,7 +42,36 @@ pub struct Entry {
     /// Checksum of file (required for LFN entries)
     checksum: u8,                   
     /// Deletion marker (0xe5) set? [yes/no]
-    deleted: bool                   
+    deleted: bool,
+    /// The 11 bytes of the short name as stored in the directory entry
+    raw_name: [u8; 11],
+    /// Has the first character of a deleted name been restored from its LFN entries? [yes/no]
+    restored: bool,
+    /// Byte offset of the directory entry within the volume. One can add the
+    /// address by invoking add_address()
+    address: Option<usize>,
+}
+
+/// Characters that can replace the deletion marker (0x05 stands for 0xe5)
+const FIRST_CHAR_CANDIDATES: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789!#$%&'()-@^_`{}~\x05";
+
+/// The character 0xe5 of code page 437, stored as 0x05 in the first byte of a name
+const ESCAPED_E5: char = 'σ';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// A decoded FAT date and time
+///
+/// FAT stores dates as days since 1980-01-01 packed into 16 bits and times
+/// with a resolution of two seconds (the creation time has an additional
+/// field with a resolution of 10 ms).
+pub struct Timestamp {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub millis: u16,
 }
 
 #[derive(Debug)]
@@ -49,13 +79,13 @@ pub struct Entry {
 /// Represents a Long File Name entry (LFN)
 ///
 /// FAT uses LFN entries to store long file names (> 11 Bytes).
-/// LFN entries store the file name in utf-8 unicode.
+/// LFN entries store the file name in UTF-16 (little endian).
 pub struct LFNEntry {
     /// An entry can have multiple associated LFN entries.
     /// The sequence number is used to order all LFN entries
     /// belonging to a file.
     sequence_number: u8,
-    /// The long file name (or part of it) in utf-8
+    /// The long file name (or part of it)
     filename: String,
     /// A checksum is calculated from the short file name of
     /// the actual directory entry and stored within an LFN entry.
@@ -74,7 +104,7 @@ impl Entry {
     /// # Examples
     ///
     /// ```
-    /// use greasy::formats::fat::Entry;
+    /// use greasy::formats::fat_entry::Entry;
     ///
     /// let alice = "Alice      ";
     /// let work = "WORK       ";
@@ -83,9 +113,20 @@ impl Entry {
     /// assert_eq!(163, Entry::checksum(work));
     /// ```
     pub fn checksum(s: &str) -> u8 {
+        Entry::checksum_bytes(s.as_bytes())
+    }
+
+    /// Calculate the checksum of the raw 11 byte short name
+    ///
+    /// Unlike checksum() this works for names that aren't valid UTF-8.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The 11 bytes of the short name as stored in the directory entry
+    pub fn checksum_bytes(name: &[u8]) -> u8 {
         let mut checksum: u16 = 0;
 
-        for c in s.as_bytes() {
+        for c in name {
             checksum = (((checksum & 1) << 7 | (checksum >> 1)) + *c as u16) % 256;
         }
 
@@ -98,15 +139,12 @@ impl Entry {
     ///
     /// * `mem` - A byte slice representing the entry in memory (Expected to be 32-Bytes)
     pub fn new(mem: &[u8]) -> Entry {
-        /*
-        let name = CString::new(&mem[..11]).expect("Parsing name field failed")
-                                          .into_string()
-                                          .expect("Translation from CString to String failed");
-                                          */
-        let name = String::from_utf8_lossy(&mem[..11]).to_string();
-        Entry {
-            attributes: mem[11] as u8,
-            creat_tos: mem[13] as u8,
+        let mut raw_name = [0u8; 11];
+        raw_name.copy_from_slice(&mem[..11]);
+
+        let mut entry = Entry {
+            attributes: mem[11],
+            creat_tos: mem[13],
             creat_hms: LittleEndian::read_u16(&mem[14..16]),
             creat_day: LittleEndian::read_u16(&mem[16..18]),
             access_day: LittleEndian::read_u16(&mem[18..20]),
@@ -116,14 +154,26 @@ impl Entry {
                              LittleEndian::read_u16(&mem[26..28]) as u32),
             clusters: None,
             size: LittleEndian::read_u32(&mem[28..32]),
-            checksum: Entry::checksum(&name),
-            deleted: match mem[0] {
-                0xe5 => true,
-                _ => false,
-            },
+            checksum: Entry::checksum_bytes(&mem[..11]),
+            deleted: mem[0] == 0xe5,
             long_name: None,
-            name: name,
+            address: None,
+            name: String::from_utf8_lossy(&mem[..11]).to_string(),
+            raw_name,
+            restored: false,
+        };
+
+        if mem[0] == 0x05 {
+            entry.set_first_char(ESCAPED_E5);
         }
+
+        entry
+    }
+
+    /// Replace the first character of the short name
+    fn set_first_char(&mut self, c: char) {
+        let rest: String = self.name.chars().skip(1).collect();
+        self.name = format!("{}{}", c, rest);
     }
 
     /// Checks if entry is a disk volume entry 
@@ -141,40 +191,67 @@ impl Entry {
         self.deleted
     }
 
-    pub fn is_this_entry(&self) -> bool {
-        self.name.trim() == "."
+    /// Returns the long file name if present, the short name otherwise
+    pub fn name(&self) -> String {
+        match &self.long_name {
+            Some(n) => n.clone(),
+            None => self.dotted_name(),
+        }
     }
 
-    pub fn is_prev_entry(&self) -> bool {
-        self.name.trim() == ".."
-    }
-    
-    /// Returns the string representation of the given entry
-    pub fn to_string(&self) -> String {
-        let entry_type;
-        let del;
-        let name = match &self.long_name {
-            Some(n) => n,
-            None => self.name.trim(),
-        };
+    /// Returns the short name in its dotted form (e.g. `README.TXT`)
+    ///
+    /// Volume labels aren't split into name and extension.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use greasy::formats::fat_entry::Entry;
+    ///
+    /// let mut mem = [0u8; 32];
+    /// mem[..11].copy_from_slice(b"README  TXT");
+    ///
+    /// assert_eq!("README.TXT", Entry::new(&mem).dotted_name());
+    /// ```
+    pub fn dotted_name(&self) -> String {
+        let base: String = self.name.chars().take(8).collect();
+        let ext: String = self.name.chars().skip(8).collect();
+        let (base, ext) = (base.trim_end(), ext.trim_end());
 
-        if self.is_disk_volume_entry() {
-            entry_type = "V"; // Disk Volume
-        } else if self.is_subdir_entry() {
-            entry_type = "D"; // Directory
+        if ext.is_empty() || self.is_disk_volume_entry() {
+            self.name.trim_end().to_string()
         } else {
-            entry_type = "F"; // File
+            format!("{}.{}", base, ext)
         }
+    }
 
-        if self.deleted {
-            del = "X | ";
-        } else {
-            del = "";
-        }
+    /// Checks if a path component names this entry
+    ///
+    /// The component is compared case-insensitively with the long name and
+    /// with the short name in its dotted form (e.g. `README.TXT`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use greasy::formats::fat_entry::Entry;
+    ///
+    /// let mut mem = [0u8; 32];
+    /// mem[..11].copy_from_slice(b"README  TXT");
+    /// let e = Entry::new(&mem);
+    ///
+    /// assert!(e.matches("readme.txt"));
+    /// assert!(!e.matches("readme"));
+    /// ```
+    pub fn matches(&self, component: &str) -> bool {
+        component.eq_ignore_ascii_case(&self.dotted_name()) || component.to_lowercase() == self.name().to_lowercase()
+    }
 
-        
+    pub fn is_this_entry(&self) -> bool {
+        self.name.trim() == "."
+    }
 
-        format!("[{}: {}{}]", name, del, entry_type)
+    pub fn is_prev_entry(&self) -> bool {
+        self.name.trim() == ".."
     }
     
     /// Add the LFN name to the entry
@@ -186,19 +263,163 @@ impl Entry {
     /// The LFN entries are sorted based on their sequencing number and then
     /// concatendated to build a single string. That string is then assigned to
     /// the long_name filed of the given entry.
+    ///
+    /// The sequence numbers of deleted LFN entries are overwritten with 0xe5,
+    /// so those are concatenated in reverse order of storage instead.
     pub fn add_lfn(&mut self, lfns: &mut HashMap<u8, Vec<LFNEntry>>) {
         if let Some(lfn_vec) = lfns.get_mut(&self.checksum) {
-            let mut s = String::new();
-            lfn_vec.sort();
+            self.long_name = Some(LFNEntry::join(lfn_vec));
+        }
+    }
+
+    /// Restore the first character of a deleted short name
+    ///
+    /// Deleting an entry overwrites the first byte of its short name with
+    /// 0xe5. Every candidate character is substituted and the checksum of the
+    /// resulting name is compared with the checksums of the deleted LFN
+    /// entries of the directory. A match is accepted if the candidate equals
+    /// the first character of the long name (or if it's the only match and
+    /// the long name starts with a non-ASCII character). Otherwise the
+    /// placeholder is used instead. Call this before add_lfn(), so the
+    /// matching LFN entries are attached as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `lfns` - A hash map that maps from a cheksum to a vector of LFN entries
+    /// * `placeholder` - Replaces the first character if it can't be restored
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use greasy::formats::fat_entry::{Entry, LFNEntry};
+    /// use std::collections::HashMap;
+    ///
+    /// let mut lfn = [0xffu8; 32];
+    /// lfn[0] = 0xe5;
+    /// lfn[11] = 0x0f;
+    /// lfn[13] = Entry::checksum("WORK    JPG");
+    /// for (i, c) in "work.jpg\0".encode_utf16().take(5).enumerate() {
+    ///     lfn[1 + 2 * i..3 + 2 * i].copy_from_slice(&c.to_le_bytes());
+    /// }
+    /// let mut lfns = HashMap::new();
+    /// lfns.insert(lfn[13], vec![LFNEntry::new(&lfn)]);
+    ///
+    /// let mut mem = [0u8; 32];
+    /// mem[..11].copy_from_slice(b"\xe5ORK    JPG");
+    /// let mut e = Entry::new(&mem);
+    /// e.restore_name(&lfns, '_');
+    /// assert_eq!("WORK.JPG", e.dotted_name());
+    ///
+    /// mem[..11].copy_from_slice(b"\xe5EST    TXT");
+    /// let mut e = Entry::new(&mem);
+    /// e.restore_name(&lfns, '_');
+    /// assert_eq!("_EST.TXT", e.dotted_name());
+    /// ```
+    pub fn restore_name(&mut self, lfns: &HashMap<u8, Vec<LFNEntry>>, placeholder: char) {
+        if !self.deleted || self.restored {
+            return;
+        }
+
+        let candidates: Vec<(u8, String)> = FIRST_CHAR_CANDIDATES.iter()
+            .filter_map(|c| {
+                let mut name = self.raw_name;
+                name[0] = *c;
+                lfns.get(&Entry::checksum_bytes(&name))
+                    .filter(|lfn_vec| lfn_vec.iter().any(|l| l.sequence_number == 0xe5))
+                    .map(|lfn_vec| (*c, LFNEntry::join(lfn_vec)))
+            })
+            .collect();
 
-            for e in lfn_vec {
-                s.push_str(&e.filename);
-            }
+        // the short name is derived from the long name, a mismatching first letter is a checksum collision
+        let first_char = |long: &str| long.chars().find(|c| *c != '.' && *c != ' ').map(|c| c.to_ascii_uppercase());
+        let found = candidates.iter()
+            .find(|(c, long)| first_char(long) == Some(*c as char))
+            .or(match &candidates[..] {
+                [only] if first_char(&only.1).map_or(true, |c| !c.is_ascii()) => Some(only),
+                _ => None,
+            });
 
-            self.long_name = Some(s);
+        match found {
+            Some((c, _)) => {
+                self.raw_name[0] = *c;
+                self.checksum = Entry::checksum_bytes(&self.raw_name);
+                self.restored = true;
+                self.set_first_char(if *c == 0x05 { ESCAPED_E5 } else { *c as char });
+            },
+            None => self.set_first_char(placeholder),
         }
     }
 
+    /// Checks if the first character of a deleted name has been restored from its LFN entries
+    pub fn is_name_restored(&self) -> bool {
+        self.restored
+    }
+
+    /// Add the byte offset of the directory entry within the volume
+    pub fn add_address(&mut self, address: usize) {
+        self.address = Some(address);
+    }
+
+    /// Returns the byte offset of the directory entry within the volume
+    pub fn address(&self) -> Option<usize> {
+        self.address
+    }
+
+    /// Returns the attribute byte of the entry
+    pub fn attributes(&self) -> u8 {
+        self.attributes
+    }
+
+    /// Checks if the read only attribute is set
+    pub fn is_read_only(&self) -> bool {
+        (self.attributes & 0x01) != 0
+    }
+
+    /// Checks if the hidden attribute is set
+    pub fn is_hidden(&self) -> bool {
+        (self.attributes & 0x02) != 0
+    }
+
+    /// Checks if the system attribute is set
+    pub fn is_system(&self) -> bool {
+        (self.attributes & 0x04) != 0
+    }
+
+    /// Checks if the archive attribute is set
+    pub fn is_archive(&self) -> bool {
+        (self.attributes & 0x20) != 0
+    }
+
+    /// Returns the attributes as a string of flags (`RHSVDA`, unset flags are `-`)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use greasy::formats::fat_entry::Entry;
+    ///
+    /// let mut mem = [0u8; 32];
+    /// mem[..11].copy_from_slice(b"README  TXT");
+    /// mem[11] = 0x21;
+    ///
+    /// assert_eq!("R----A", Entry::new(&mem).flags());
+    /// ```
+    pub fn flags(&self) -> String {
+        "RHSVDA".chars()
+            .enumerate()
+            .map(|(i, c)| if self.attributes & (1 << i) != 0 { c } else { '-' })
+            .collect()
+    }
+
+    /// Returns the long file name (None if the entry has no LFN entries)
+    pub fn long_name(&self) -> Option<&str> {
+        self.long_name.as_deref()
+    }
+
+    /// Returns the 11 byte short name as stored in the directory entry
+    pub fn short_name(&self) -> &str {
+        &self.name
+    }
+
     pub fn add_clusters(&mut self, clusters: Vec<Cluster>) {
         self.clusters = Some(clusters);
     }
@@ -210,6 +431,132 @@ impl Entry {
     pub fn start(&self) -> &Cluster {
         &self.start
     }
+
+    /// Returns the file size (in bytes)
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Returns the raw creation time as (tenths of seconds, hms, day)
+    pub fn created_raw(&self) -> (u8, u16, u16) {
+        (self.creat_tos, self.creat_hms, self.creat_day)
+    }
+
+    /// Returns the raw last access day
+    pub fn accessed_raw(&self) -> u16 {
+        self.access_day
+    }
+
+    /// Returns the raw last write time as (hms, day)
+    pub fn written_raw(&self) -> (u16, u16) {
+        (self.written_hms, self.written_day)
+    }
+
+    /// Returns the creation time (None if not set or invalid)
+    pub fn created(&self) -> Option<Timestamp> {
+        Timestamp::new(self.creat_day, self.creat_hms, self.creat_tos)
+    }
+
+    /// Returns the last access date (None if not set or invalid)
+    pub fn accessed(&self) -> Option<Timestamp> {
+        Timestamp::new(self.access_day, 0, 0)
+    }
+
+    /// Returns the last write time (None if not set or invalid)
+    pub fn written(&self) -> Option<Timestamp> {
+        Timestamp::new(self.written_day, self.written_hms, 0)
+    }
+}
+
+impl Timestamp {
+    /// Decode a FAT date and time
+    ///
+    /// Returns None if the date is zero (not set) or if any field is out of range.
+    ///
+    /// # Arguments
+    ///
+    /// * `day` - Date (bits 15-9: years since 1980, 8-5: month, 4-0: day)
+    /// * `hms` - Time (bits 15-11: hours, 10-5: minutes, 4-0: seconds / 2)
+    /// * `tens` - Additional time in units of 10 ms (0 - 199)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use greasy::formats::fat_entry::Timestamp;
+    ///
+    /// let t = Timestamp::new(0x50e4, 0x6cb5, 100).unwrap();
+    /// assert_eq!("2020-07-04 13:37:43", t.to_string());
+    /// assert_eq!(0, t.millis);
+    ///
+    /// assert!(Timestamp::new(0, 0, 0).is_none());
+    /// ```
+    pub fn new(day: u16, hms: u16, tens: u8) -> Option<Timestamp> {
+        let millis = (tens as u16 % 200) * 10;
+        let t = Timestamp {
+            year: 1980 + (day >> 9),
+            month: ((day >> 5) & 0x0f) as u8,
+            day: (day & 0x1f) as u8,
+            hour: (hms >> 11) as u8,
+            minute: ((hms >> 5) & 0x3f) as u8,
+            second: ((hms & 0x1f) * 2 + millis / 1000) as u8,
+            millis: millis % 1000,
+        };
+
+        if day == 0 || t.month == 0 || t.month > 12 || t.day == 0 || t.hour > 23 || t.minute > 59 || t.second > 59 {
+            None
+        } else {
+            Some(t)
+        }
+    }
+
+    /// Formats the timestamp as ISO 8601 (`YYYY-MM-DDThh:mm:ss.sss`)
+    ///
+    /// FAT doesn't store a time zone, the timestamp is local time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use greasy::formats::fat_entry::Timestamp;
+    ///
+    /// let t = Timestamp::new(0x50e4, 0x6cb5, 105).unwrap();
+    /// assert_eq!("2020-07-04T13:37:43.050", t.to_iso8601());
+    /// ```
+    pub fn to_iso8601(&self) -> String {
+        format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}", self.year, self.month, self.day, self.hour, self.minute, self.second, self.millis)
+    }
+}
+
+impl Serialize for Timestamp {
+    /// Serializes the timestamp as ISO 8601 string
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_iso8601())
+    }
+}
+
+impl fmt::Display for Timestamp {
+    /// Formats the timestamp as `YYYY-MM-DD hh:mm:ss`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}", self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+impl fmt::Display for Entry {
+    /// Formats the entry as `[name: type]` where deleted entries are marked with `X`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.name();
+
+        let entry_type = if self.is_disk_volume_entry() {
+            "V" // Disk Volume
+        } else if self.is_subdir_entry() {
+            "D" // Directory
+        } else {
+            "F" // File
+        };
+
+        let del = if self.deleted { "X | " } else { "" };
+
+        write!(f, "[{}: {}{}]", name, del, entry_type)
+    }
 }
 
 impl LFNEntry {
@@ -218,25 +565,73 @@ impl LFNEntry {
     /// # Arguments
     ///
     /// * `mem` - A byte slice representing the entry in memory (Expected to be 32-Bytes)
+    ///
+    /// The 13 UTF-16 characters are stored in three parts (bytes 1-10, 14-25
+    /// and 28-31). The name ends at the first 0x0000, unused characters are
+    /// padded with 0xffff.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use greasy::formats::fat_entry::LFNEntry;
+    ///
+    /// let mut mem = [0xffu8; 32];
+    /// mem[0] = 0x41;
+    /// mem[11] = 0x0f;
+    /// mem[13] = 0xa3;
+    /// for (i, c) in "Wörk\0".encode_utf16().enumerate() {
+    ///     mem[1 + 2 * i..3 + 2 * i].copy_from_slice(&c.to_le_bytes());
+    /// }
+    ///
+    /// let lfn = LFNEntry::new(&mem);
+    /// assert_eq!("Wörk", lfn.filename());
+    /// assert_eq!(0xa3, lfn.checksum());
+    /// ```
     pub fn new(mem: &[u8]) -> LFNEntry {
-        let mut s1 = String::from_utf8_lossy(&mem[1..11]).to_string();
-        let s2 = String::from_utf8_lossy(&mem[14..26]).to_string();
-        let s3 = String::from_utf8_lossy(&mem[28..32]).to_string();
-
-        s1.push_str(&s2);
-        s1.push_str(&s3);
+        let chars: Vec<u16> = [&mem[1..11], &mem[14..26], &mem[28..32]].concat()
+            .chunks(2)
+            .map(LittleEndian::read_u16)
+            .take_while(|c| *c != 0x0000)
+            .filter(|c| *c != 0xffff)
+            .collect();
 
         LFNEntry {
             sequence_number: mem[0],
-            filename: s1.trim_matches(|c| c == std::char::REPLACEMENT_CHARACTER).to_string(),
+            filename: String::from_utf16_lossy(&chars),
             checksum: mem[13],
         }
     }
 
+    /// Returns the part of the long file name stored in this entry
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
     pub fn checksum(&self) -> u8 {
         self.checksum
     }
+
+    /// Returns the sequence number (bit 6 marks the last LFN entry of a name)
+    pub fn sequence_number(&self) -> u8 {
+        self.sequence_number
+    }
     
+    /// Concatenate the parts of a long file name
+    ///
+    /// Entries are ordered by their sequence number. Deleted entries (0xe5)
+    /// are stored in front of the short entry with the last part first, so
+    /// they are concatenated in reverse order of storage.
+    fn join(lfn_vec: &[LFNEntry]) -> String {
+        let mut parts: Vec<&LFNEntry> = lfn_vec.iter().collect();
+        if parts.iter().any(|l| l.sequence_number == 0xe5) {
+            parts.reverse();
+        } else {
+            parts.sort();
+        }
+
+        parts.iter().map(|l| l.filename.as_str()).collect()
+    }
+
     /// Checks if the a attributes indicate an LFN entry
     ///
     /// # Arguments
@@ -246,3 +641,58 @@ impl LFNEntry {
         attributes == 0x0f
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fat_test::named_entry;
+
+    /// Returns the deleted short entry and its LFN entries grouped by checksum
+    fn deleted(long: &str, name: &[u8; 11]) -> (Entry, HashMap<u8, Vec<LFNEntry>>) {
+        let mut raw = named_entry(long, name, 0x20, 0, 0, true);
+        let short = raw.pop().unwrap();
+        let mut lfns = HashMap::new();
+        lfns.insert(Entry::checksum_bytes(name), raw.iter().map(|l| LFNEntry::new(l)).collect());
+
+        (Entry::new(&short), lfns)
+    }
+
+    #[test]
+    fn restores_escaped_e5() {
+        let (mut e, lfns) = deleted("σigma.txt", b"\x05IGMA   TXT");
+        e.restore_name(&lfns, '_');
+
+        assert!(e.is_name_restored());
+        assert_eq!(e.dotted_name(), "σIGMA.TXT");
+        assert_eq!((e.raw_name[0], e.checksum), (0x05, Entry::checksum_bytes(b"\x05IGMA   TXT")));
+    }
+
+    #[test]
+    fn ignores_checksum_collision_with_other_first_letter() {
+        // the LFN entries of WORK.JPG happen to match the checksum of BEST.TXT
+        let (_, work) = deleted("work.jpg", b"WORK    JPG");
+        let lfns: HashMap<u8, Vec<LFNEntry>> = work.into_values().map(|l| (Entry::checksum_bytes(b"BEST    TXT"), l)).collect();
+
+        let mut e = Entry::new(&named_entry("", b"BEST    TXT", 0x20, 0, 0, true)[0]);
+        e.restore_name(&lfns, '_');
+
+        assert!(!e.is_name_restored());
+        assert_eq!(e.dotted_name(), "_EST.TXT");
+    }
+
+    #[test]
+    fn falls_back_to_placeholder() {
+        let (mut e, _) = deleted("test.txt", b"TEST    TXT");
+        e.restore_name(&HashMap::new(), '#');
+        assert!(!e.is_name_restored());
+        assert_eq!(e.dotted_name(), "#EST.TXT");
+
+        // LFN entries of a live file don't belong to the deleted entry
+        let (mut e, mut lfns) = deleted("test.txt", b"TEST    TXT");
+        for l in lfns.values_mut().flatten() {
+            l.sequence_number = 0x41;
+        }
+        e.restore_name(&lfns, '#');
+        assert_eq!(e.dotted_name(), "#EST.TXT");
+    }
+}
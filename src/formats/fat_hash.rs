@@ -0,0 +1,302 @@
+use super::fat::*;
+use super::fat_reader::FileReader;
+use super::fat_recover;
+use super::fat_walk::{DeletedDirs, WalkOptions};
+use md5::Md5;
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashSet,
+    fmt,
+    io::{self, BufRead, Read},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// MD5, SHA-1 and SHA-256 digest of a byte stream (lower case hex)
+pub struct Hashes {
+    pub md5: String,
+    pub sha1: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Classification of a file by its hashes
+pub enum Status {
+    /// Matches a known-good hash (e.g. operating system files)
+    Known,
+    /// Matches a known-bad hash
+    Notable,
+    /// Isn't part of any hash set
+    Unknown,
+}
+
+#[derive(Debug, Default)]
+/// Sets of known-good and known-bad hashes (MD5, SHA-1 or SHA-256, lower case hex)
+pub struct HashDatabase {
+    known: HashSet<String>,
+    notable: HashSet<String>,
+}
+
+#[derive(Debug)]
+/// The hashes of a single file
+pub struct FileHash {
+    /// Absolute path of the file
+    pub path: String,
+    /// Is the file or one of its ancestors deleted? [yes/no]
+    pub deleted: bool,
+    /// Was the content of a deleted file read from its presumed clusters? [yes/no]
+    pub recovered: bool,
+    pub hashes: Hashes,
+    pub status: Status,
+}
+
+impl Hashes {
+    /// Hash everything that can be read from `reader` in a single pass
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The byte stream (e.g. a `FileReader`)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use greasy::formats::fat_hash::Hashes;
+    ///
+    /// let h = Hashes::compute(&mut &b"abc"[..]).unwrap();
+    /// assert_eq!("900150983cd24fb0d6963f7d28e17f72", h.md5);
+    /// assert_eq!("a9993e364706816aba3e25717850c26c9cd0d89d", h.sha1);
+    /// ```
+    pub fn compute<R: Read>(reader: &mut R) -> io::Result<Hashes> {
+        let mut md5 = Md5::new();
+        let mut sha1 = Sha1::new();
+        let mut sha256 = Sha256::new();
+        let mut buf = [0u8; 64 * 1024];
+
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            md5.update(&buf[..n]);
+            sha1.update(&buf[..n]);
+            sha256.update(&buf[..n]);
+        }
+
+        Ok(Hashes {
+            md5: format!("{:x}", md5.finalize()),
+            sha1: format!("{:x}", sha1.finalize()),
+            sha256: format!("{:x}", sha256.finalize()),
+        })
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Status::Known => "known",
+            Status::Notable => "notable",
+            Status::Unknown => "unknown",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl HashDatabase {
+    /// Add all hashes of a hash list to the known-good set
+    pub fn load_known<R: BufRead>(&mut self, reader: R) -> io::Result<usize> {
+        let hashes = read_hashes(reader)?;
+        let n = hashes.len();
+        self.known.extend(hashes);
+        Ok(n)
+    }
+
+    /// Add all hashes of a hash list to the known-bad set
+    pub fn load_notable<R: BufRead>(&mut self, reader: R) -> io::Result<usize> {
+        let hashes = read_hashes(reader)?;
+        let n = hashes.len();
+        self.notable.extend(hashes);
+        Ok(n)
+    }
+
+    /// Classify a file by its hashes (known-bad wins over known-good)
+    pub fn lookup(&self, hashes: &Hashes) -> Status {
+        let all = [&hashes.md5, &hashes.sha1, &hashes.sha256];
+
+        if all.iter().any(|h| self.notable.contains(*h)) {
+            Status::Notable
+        } else if all.iter().any(|h| self.known.contains(*h)) {
+            Status::Known
+        } else {
+            Status::Unknown
+        }
+    }
+}
+
+/// Checks if a string is a hex encoded MD5, SHA-1 or SHA-256
+fn is_hash(s: &str) -> bool {
+    matches!(s.len(), 32 | 40 | 64) && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Read a hash list
+///
+/// Two formats are supported:
+/// * plain lists with one hash per line (anything after the first whitespace,
+///   empty lines and lines starting with `#` are ignored)
+/// * NSRL-style CSV files whose header names the columns (`"SHA-1","MD5",...`);
+///   the values of the SHA-1, MD5 and SHA-256 columns are read
+///
+/// # Examples
+///
+/// ```
+/// use greasy::formats::fat_hash::read_hashes;
+///
+/// let plain = "# known files\n900150983CD24FB0D6963F7D28E17F72  abc.txt\n";
+/// assert_eq!(vec!["900150983cd24fb0d6963f7d28e17f72"], read_hashes(plain.as_bytes()).unwrap());
+///
+/// let nsrl = "\"SHA-1\",\"MD5\",\"CRC32\",\"FileName\"\n\
+///             \"A9993E364706816ABA3E25717850C26C9CD0D89D\",\"900150983CD24FB0D6963F7D28E17F72\",\"352441C2\",\"abc.txt\"\n";
+/// assert_eq!(2, read_hashes(nsrl.as_bytes()).unwrap().len());
+/// ```
+pub fn read_hashes<R: BufRead>(reader: R) -> io::Result<Vec<String>> {
+    let mut lines = reader.lines().peekable();
+    let mut hashes = Vec::new();
+
+    let header = match lines.peek() {
+        Some(Ok(line)) if line.starts_with('"') => line.clone(),
+        _ => String::new(),
+    };
+
+    if !header.is_empty() {
+        let columns: Vec<usize> = header.split(',')
+            .enumerate()
+            .filter(|(_, c)| matches!(c.trim_matches('"').to_uppercase().as_str(), "SHA-1" | "MD5" | "SHA-256" | "SHA256"))
+            .map(|(i, _)| i)
+            .collect();
+
+        for line in lines.skip(1) {
+            let line = line?;
+            let fields: Vec<&str> = line.split(',').map(|f| f.trim_matches('"')).collect();
+            hashes.extend(columns.iter()
+                .filter_map(|i| fields.get(*i))
+                .filter(|f| is_hash(f))
+                .map(|f| f.to_lowercase()));
+        }
+    } else {
+        for line in lines {
+            let line = line?;
+            match line.split_whitespace().next() {
+                Some(h) if is_hash(h) => hashes.push(h.to_lowercase()),
+                _ => (),
+            }
+        }
+    }
+
+    Ok(hashes)
+}
+
+/// Hash every file of the volume
+///
+/// Deleted files (and files of deleted directories) are hashed if their
+/// presumed clusters haven't been allocated again, otherwise they are skipped.
+/// Deleted empty files hash the empty stream. Files that can't be read (e.g. a
+/// live entry whose start cluster is free or whose chain leaves the image) are
+/// returned as errors.
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+/// * `db` - Hash sets used to classify the files
+/// * `include_deleted` - Hash recoverable deleted files
+pub fn hash_files(fat: &dyn FAT, db: &HashDatabase, include_deleted: bool) -> Vec<io::Result<FileHash>> {
+    let base = fat.fat();
+    let mut files = Vec::new();
+    let mut deleted_dirs = DeletedDirs::default();
+
+    for item in fat.walk(WalkOptions { include_deleted, ..WalkOptions::default() }) {
+        let deleted = deleted_dirs.is_deleted(&item);
+        let e = item.entry;
+
+        if e.is_subdir_entry() || e.is_disk_volume_entry() {
+            continue;
+        }
+
+        let recovered = deleted && e.size() > 0;
+        let hashes = if deleted && e.size() == 0 {
+            Hashes::compute(&mut io::empty())
+        } else if recovered {
+            match fat_recover::recover(base, &e) {
+                Some(r) => Hashes::compute(&mut FileReader::new(base, &r)),
+                None => continue,
+            }
+        } else if fat_recover::is_unlinked(base, &e) {
+            let message = format!("{}: the start cluster {} is free although the entry isn't deleted", item.path, e.start().0);
+            files.push(Err(io::Error::new(io::ErrorKind::InvalidData, message)));
+            continue;
+        } else {
+            Hashes::compute(&mut FileReader::new(base, &e))
+        };
+
+        files.push(match hashes {
+            Ok(hashes) => Ok(FileHash {
+                path: item.path,
+                deleted,
+                recovered,
+                status: db.lookup(&hashes),
+                hashes,
+            }),
+            Err(err) => Err(io::Error::new(err.kind(), format!("{}: {}", item.path, err))),
+        });
+    }
+
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fat_test::*;
+
+    #[test]
+    fn live_entry_with_free_start_cluster_is_an_error() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"LIVE    TXT", 0x20, 3, 100)]);
+
+        let files = hash_files(&*image.open(), &HashDatabase::default(), true);
+
+        assert_eq!(files.len(), 1);
+        assert!(files[0].as_ref().unwrap_err().to_string().starts_with("/LIVE.TXT: the start cluster 3 is free"));
+    }
+
+    #[test]
+    fn files_of_deleted_directories_are_recovered() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"\xe5IR        ", 0x10, 3, 0)]);
+        image.set_dir(3, 0, &[
+            short_entry(b".          ", 0x10, 3, 0),
+            short_entry(b"..         ", 0x10, 0, 0),
+            short_entry(b"FILE    TXT", 0x20, 4, 3),
+        ]);
+        image.write(Image::cluster_offset(4), b"abc");
+
+        let files = hash_files(&*image.open(), &HashDatabase::default(), true);
+        let file = files.iter().find_map(|f| f.as_ref().ok()).unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert!(file.recovered && file.deleted);
+        assert_eq!(file.hashes.md5, "900150983cd24fb0d6963f7d28e17f72");
+    }
+
+    #[test]
+    fn deleted_empty_file_hashes_the_empty_stream() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"\xe5MPTY   TXT", 0x20, 0, 0)]);
+
+        let files = hash_files(&*image.open(), &HashDatabase::default(), true);
+        let file = files[0].as_ref().unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert!(file.deleted && !file.recovered);
+        assert_eq!(file.hashes.md5, "d41d8cd98f00b204e9800998ecf8427e");
+    }
+}
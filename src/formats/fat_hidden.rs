@@ -0,0 +1,126 @@
+use super::fat::*;
+use super::fat_table::cluster_runs;
+use serde::Serialize;
+use std::{cmp, fmt};
+
+#[derive(Debug, Clone, Serialize)]
+/// A region of the volume that isn't used by the file system
+pub struct HiddenRegion {
+    /// Description of the region (e.g. `FAT 0: entries beyond the last cluster`)
+    pub name: String,
+    /// Byte offset of the region within the image
+    pub offset: usize,
+    /// Length of the region (in bytes)
+    pub length: usize,
+    /// Number of non-zero bytes
+    pub non_zero: usize,
+    /// Offset of the first non-zero byte within the image
+    pub first_non_zero: Option<usize>,
+}
+
+impl HiddenRegion {
+    /// Inspect the bytes from `start` up to `end` (exclusive, cut off at the end of the image)
+    fn new(fat: &Fat, name: &str, start: usize, end: usize) -> Option<HiddenRegion> {
+        let end = cmp::min(end, fat.mem().len());
+        if start >= end {
+            return None;
+        }
+
+        let data = &fat.mem()[start..end];
+
+        Some(HiddenRegion {
+            name: name.to_string(),
+            offset: start,
+            length: end - start,
+            non_zero: data.iter().filter(|b| **b != 0).count(),
+            first_non_zero: data.iter().position(|b| *b != 0).map(|i| start + i),
+        })
+    }
+
+    /// Checks if the region contains any data
+    pub fn has_data(&self) -> bool {
+        self.non_zero > 0
+    }
+}
+
+impl fmt::Display for HiddenRegion {
+    /// Formats the region as a single tab separated line:
+    /// status, offset, length, number of non-zero bytes and name
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = if self.has_data() { "DATA" } else { "empty" };
+        write!(f, "{}\t0x{:x}\t{}\t{}\t{}", status, self.offset, self.length, self.non_zero, self.name)
+    }
+}
+
+/// Returns the sectors of the reserved area that are used by the boot record
+///
+/// This is the boot sector on FAT16. On FAT32 the boot record spans three
+/// sectors (boot sector, FSInfo, boot code) and is repeated at the backup
+/// boot sector.
+fn boot_record_sectors(fat: &Fat) -> Vec<u32> {
+    let boot = fat.boot_sector();
+    if !boot.is_fat32() {
+        return vec![0];
+    }
+
+    let mut sectors = vec![0, 1, 2, boot.fs_info_sector as u32];
+    if boot.backup_boot_sector != 0 && boot.backup_boot_sector != 0xffff {
+        let backup = boot.backup_boot_sector as u32;
+        sectors.extend([backup, backup + 1, backup + 2]);
+    }
+
+    sectors
+}
+
+/// Inspect all regions of the volume that aren't used by the file system
+///
+/// The following regions are inspected:
+///
+/// * unused sectors of the reserved area (outside of the boot record)
+/// * FAT entries beyond the last cluster up to the end of their sector
+/// * whole sectors at the end of each FAT table that hold no entry of a valid cluster
+/// * slots of the FAT16 root directory after the end of directory marker
+/// * sectors at the end of the cluster area that don't form a whole cluster
+/// * data after the last sector of the volume
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+pub fn hidden_regions(fat: &Fat) -> Vec<HiddenRegion> {
+    let bps = fat.bytes_per_sector() as usize;
+    let mut regions = Vec::new();
+
+    let boot_record = boot_record_sectors(fat);
+    let reserved: Vec<u32> = (1..fat.start_fat_area()).filter(|s| !boot_record.contains(s)).collect();
+    for (first, last) in cluster_runs(&reserved) {
+        let name = format!("reserved sectors {} - {}", first, last);
+        regions.extend(HiddenRegion::new(fat, &name, first as usize * bps, (last as usize + 1) * bps));
+    }
+
+    for n in 0..fat.fat_table_count() {
+        let table_start = fat.offset(&Sector(fat.start_fat_area() + n as u32 * fat.fat_table_sectors()));
+        let table_end = table_start + fat.fat_table_sectors() as usize * bps;
+        let unused = fat.fat_table_offset_in(n, &Cluster(fat.total_clusters() + 1));
+        let sector_end = cmp::min(unused.div_ceil(bps) * bps, table_end);
+
+        regions.extend(HiddenRegion::new(fat, &format!("FAT {}: entries beyond the last cluster", n), unused, sector_end));
+        regions.extend(HiddenRegion::new(fat, &format!("FAT {}: unused sectors", n), sector_end, table_end));
+    }
+
+    if !fat.boot_sector().is_fat32() {
+        let root = fat.offset(&Sector(fat.start_root_dir()));
+        let end = fat.offset(&Sector(fat.start_cluster_area()));
+        let marker = (root..end).step_by(32).find(|a| fat.mem().get(*a) == Some(&0x00));
+
+        if let Some(marker) = marker {
+            regions.extend(HiddenRegion::new(fat, "root directory: slots after the end of directory marker", marker, end));
+        }
+    }
+
+    let clusters_end = fat.clusters_to_offsets(&[Cluster(fat.total_clusters())])[0] + fat.cluster_size();
+    let volume_end = fat.offset(&Sector(fat.total_sectors()));
+    regions.extend(HiddenRegion::new(fat, "unused sectors at the end of the cluster area", clusters_end, volume_end));
+    regions.extend(HiddenRegion::new(fat, "data after the end of the volume", volume_end, fat.mem().len()));
+
+    regions
+}
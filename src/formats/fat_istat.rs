@@ -0,0 +1,158 @@
+use super::fat::*;
+use super::fat_dump::hexdump;
+use super::fat_entry::*;
+use super::fat_recover;
+use super::fat_table::cluster_runs;
+use byteorder::{ByteOrder, LittleEndian};
+use std::io::{self, Write};
+
+/// Maximum number of LFN entries of a single name (255 characters / 13)
+const MAX_LFN_ENTRIES: usize = 20;
+
+/// Returns the addresses of the LFN entries right in front of a directory entry
+///
+/// The entries are returned in the order they are stored (the last part of
+/// the name first).
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+/// * `address` - Byte offset of the directory entry within the volume
+pub fn lfn_slots(fat: &Fat, address: usize) -> Vec<usize> {
+    let mem = fat.mem();
+    let mut slots = Vec::new();
+    let mut a = address;
+
+    while a >= 32 && slots.len() < MAX_LFN_ENTRIES && LFNEntry::is_lfn_entry(mem[a - 32 + 11]) {
+        a -= 32;
+        slots.push(a);
+    }
+
+    slots.reverse();
+    slots
+}
+
+/// Decode the NT case flags (byte 12 of a directory entry)
+fn case_flags(flags: u8) -> String {
+    let mut s = Vec::new();
+    if flags & 0x08 != 0 {
+        s.push("lower case name");
+    }
+    if flags & 0x10 != 0 {
+        s.push("lower case extension");
+    }
+
+    if s.is_empty() { "-".to_string() } else { s.join(", ") }
+}
+
+/// Decode the attribute byte
+fn attributes(attr: u8) -> String {
+    let names = ["read only", "hidden", "system", "volume label", "directory", "archive"];
+    let set: Vec<&str> = names.iter()
+        .enumerate()
+        .filter(|(i, _)| attr & (1 << i) != 0)
+        .map(|(_, n)| *n)
+        .collect();
+
+    if attr == 0x0f { "long file name".to_string() } else { set.join(", ") }
+}
+
+fn timestamp(t: Option<Timestamp>) -> String {
+    t.map(|t| t.to_string()).unwrap_or_else(|| "-".to_string())
+}
+
+/// Display all details of a single directory entry
+///
+/// The output contains the raw 32 byte entry with annotated fields, all LFN
+/// entries of the name, the decoded attributes and timestamps and the
+/// cluster chain as runs of consecutive clusters with their sector ranges.
+///
+/// # Arguments
+///
+/// * `w` - Destination of the output
+/// * `fat` - The file system
+/// * `e` - The directory entry (must have an address)
+/// * `path` - Path of the entry if known
+pub fn istat<W: Write>(w: &mut W, fat: &Fat, e: &Entry, path: Option<&str>) -> io::Result<()> {
+    let address = e.address().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "entry has no address"))?;
+    let raw = &fat.mem()[address..address + 32];
+    let bps = fat.bytes_per_sector() as usize;
+
+    writeln!(w, "DIRECTORY ENTRY\n--------------------------------")?;
+    writeln!(w, "Path: {}", path.unwrap_or("-"))?;
+    writeln!(w, "Name: {}", e.name())?;
+    if e.is_name_restored() {
+        writeln!(w, "Short Name: {} (first character restored from the LFN entries)", e.dotted_name())?;
+    }
+    writeln!(w, "Address: 0x{:x} (sector {}, offset {})", address, address / bps, address % bps)?;
+    writeln!(w, "Deleted: {}", if e.is_deleted() { "yes" } else { "no" })?;
+    writeln!(w, "\n{}", hexdump(raw, address))?;
+
+    writeln!(w, "Fields\n--------------------------------")?;
+    writeln!(w, "0x00-0x07 Name: {:?}", String::from_utf8_lossy(&raw[0..8]))?;
+    writeln!(w, "0x08-0x0a Extension: {:?}", String::from_utf8_lossy(&raw[8..11]))?;
+    writeln!(w, "0x0b      Attributes: 0x{:02x} {} ({})", raw[11], e.flags(), attributes(raw[11]))?;
+    writeln!(w, "0x0c      NT Case Flags: 0x{:02x} ({})", raw[12], case_flags(raw[12]))?;
+    writeln!(w, "0x0d      Created (10 ms): {}", raw[13])?;
+    writeln!(w, "0x0e-0x0f Created (time): 0x{:04x}", LittleEndian::read_u16(&raw[14..16]))?;
+    writeln!(w, "0x10-0x11 Created (date): 0x{:04x}", LittleEndian::read_u16(&raw[16..18]))?;
+    writeln!(w, "0x12-0x13 Accessed (date): 0x{:04x}", LittleEndian::read_u16(&raw[18..20]))?;
+    writeln!(w, "0x14-0x15 Start Cluster (high): 0x{:04x}", LittleEndian::read_u16(&raw[20..22]))?;
+    writeln!(w, "0x16-0x17 Written (time): 0x{:04x}", LittleEndian::read_u16(&raw[22..24]))?;
+    writeln!(w, "0x18-0x19 Written (date): 0x{:04x}", LittleEndian::read_u16(&raw[24..26]))?;
+    writeln!(w, "0x1a-0x1b Start Cluster (low): 0x{:04x}", LittleEndian::read_u16(&raw[26..28]))?;
+    writeln!(w, "0x1c-0x1f Size: {}", LittleEndian::read_u32(&raw[28..32]))?;
+
+    writeln!(w, "\nTimes\n--------------------------------")?;
+    writeln!(w, "Created: {}", timestamp(e.created()))?;
+    writeln!(w, "Accessed: {}", timestamp(e.accessed()))?;
+    writeln!(w, "Written: {}", timestamp(e.written()))?;
+
+    writeln!(w, "\nLong File Name Entries\n--------------------------------")?;
+    let slots = lfn_slots(fat, address);
+    if slots.is_empty() {
+        writeln!(w, "none")?;
+    }
+    for slot in slots {
+        let lfn = LFNEntry::new(&fat.mem()[slot..slot + 32]);
+        let seq = lfn.sequence_number();
+        let matches = if lfn.checksum() == Entry::checksum_bytes(&raw[..11]) { "matches" } else { "doesn't match" };
+
+        let part = if seq == 0xe5 {
+            "deleted".to_string()
+        } else {
+            format!("part {}{}", seq & 0x1f, if seq & 0x40 != 0 { ", last" } else { "" })
+        };
+
+        writeln!(w, "0x{:x}: sequence 0x{:02x} ({}), checksum 0x{:02x} ({}), {:?}",
+                 slot, seq, part, lfn.checksum(), matches, lfn.filename())?;
+    }
+
+    writeln!(w, "\nCluster Chain\n--------------------------------")?;
+    writeln!(w, "Size: {} byte(s)", e.size())?;
+    let clusters: Vec<u32> = e.clusters().iter().flatten().map(|c| c.0).collect();
+    write_runs(w, fat, &clusters)?;
+
+    if fat_recover::is_unlinked(fat, e) {
+        writeln!(w, "\nPresumed Clusters (contiguous allocation)\n--------------------------------")?;
+        let presumed: Vec<u32> = fat_recover::presumed_clusters(fat, e).iter().map(|c| c.0).collect();
+        write_runs(w, fat, &presumed)?;
+    }
+
+    Ok(())
+}
+
+/// Display clusters as runs of consecutive clusters with their sector ranges
+fn write_runs<W: Write>(w: &mut W, fat: &Fat, clusters: &[u32]) -> io::Result<()> {
+    if clusters.is_empty() {
+        return writeln!(w, "none");
+    }
+
+    for (first, last) in cluster_runs(clusters) {
+        let start = fat.cluster_to_sector(&Cluster(first)).0;
+        let end = fat.cluster_to_sector(&Cluster(last)).0 + fat.sectors_per_cluster() as u32 - 1;
+        writeln!(w, "|- Clusters {} - {} (sectors {} - {})", first, last, start, end)?;
+    }
+
+    writeln!(w, "└─ {} cluster(s) in {} run(s)", clusters.len(), cluster_runs(clusters).len())
+}
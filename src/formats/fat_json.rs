@@ -0,0 +1,178 @@
+use super::fat::*;
+use super::fat_boot::*;
+use super::fat_entry::*;
+use super::fat_walk::{Order, WalkOptions};
+use serde::Serialize;
+
+#[derive(Debug, Default, Serialize)]
+/// A JSON document holding the requested parts of the volume description
+pub struct Document {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info: Option<VolumeInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tree: Option<Vec<EntryInfo>>,
+}
+
+#[derive(Debug, Serialize)]
+/// Structured version of the information displayed by `info`
+pub struct VolumeInfo {
+    /// The FAT type (FAT16, FAT32)
+    pub fat_type: String,
+    /// Original equipment manufacturer label
+    pub oem: String,
+    /// Volume serial number
+    pub volume_id: u32,
+    /// Volume label stored in the boot sector
+    pub volume_label: String,
+    /// The boot sector used to interpret the volume
+    pub boot_sector: BootSector,
+    /// Invalid values of the boot sector
+    pub boot_sector_warnings: Vec<String>,
+    /// Is the volume interpreted using the backup boot sector? [yes/no]
+    pub boot_from_backup: bool,
+    /// Number of bytes per sector
+    pub sector_size: u16,
+    /// Number of bytes per cluster
+    pub cluster_size: usize,
+    /// Highest valid cluster number (the first one is 2)
+    pub last_cluster: u32,
+    /// The FAT table used to resolve cluster chains
+    pub active_fat: u8,
+    /// Was the volume unmounted cleanly? [yes/no]
+    pub dirty: bool,
+    /// File system layout (in sectors)
+    pub layout: Vec<Region>,
+    /// The FSInfo sector (FAT32 only)
+    pub fs_info: Option<FsInfo>,
+    /// The backup boot sector (FAT32 only)
+    pub backup_boot_sector: Option<BootSector>,
+    /// The backup FSInfo sector (FAT32 only)
+    pub backup_fs_info: Option<FsInfo>,
+    /// Fields that differ between the primary and the backup boot sector
+    pub boot_sector_diff: Vec<FieldDiff>,
+    /// Fields that differ between the primary and the backup FSInfo sector
+    pub fs_info_diff: Vec<FieldDiff>,
+}
+
+#[derive(Debug, Serialize)]
+/// A range of sectors of the volume
+pub struct Region {
+    /// Name of the region (e.g. `FAT 0`)
+    pub name: String,
+    pub first_sector: u32,
+    pub last_sector: u32,
+    /// Sub regions
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub regions: Vec<Region>,
+}
+
+#[derive(Debug, Serialize)]
+/// Structured version of a directory entry and (for directories) its content
+pub struct EntryInfo {
+    /// Absolute path of the entry
+    pub path: String,
+    /// Long name if present, short name otherwise
+    pub name: String,
+    /// The 11 byte short name as stored in the directory entry
+    pub short_name: String,
+    pub long_name: Option<String>,
+    /// `file`, `directory` or `volume`
+    pub kind: &'static str,
+    /// Raw attribute byte
+    pub attributes: u8,
+    /// Attributes as flags (`RHSVDA`)
+    pub flags: String,
+    pub deleted: bool,
+    /// File size (in bytes)
+    pub size: u32,
+    pub start_cluster: u32,
+    /// All clusters of the chain
+    pub clusters: Vec<u32>,
+    pub created: Option<Timestamp>,
+    pub accessed: Option<Timestamp>,
+    pub written: Option<Timestamp>,
+    /// Byte offset of the directory entry within the volume
+    pub address: Option<usize>,
+    /// Entries of the directory (None for files)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<EntryInfo>>,
+}
+
+impl Region {
+    /// Returns a region without sub regions
+    pub fn new(name: &str, first_sector: u32, last_sector: u32) -> Region {
+        Region { name: name.to_string(), first_sector, last_sector, regions: Vec::new() }
+    }
+}
+
+impl EntryInfo {
+    /// Returns the structured version of a directory entry (without children)
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Absolute path of the entry
+    /// * `e` - The directory entry
+    pub fn new(path: &str, e: &Entry) -> EntryInfo {
+        let kind = if e.is_disk_volume_entry() {
+            "volume"
+        } else if e.is_subdir_entry() {
+            "directory"
+        } else {
+            "file"
+        };
+
+        EntryInfo {
+            path: path.to_string(),
+            name: e.name(),
+            short_name: e.short_name().to_string(),
+            long_name: e.long_name().map(|n| n.to_string()),
+            kind,
+            attributes: e.attributes(),
+            flags: e.flags(),
+            deleted: e.is_deleted(),
+            size: e.size(),
+            start_cluster: e.start().0,
+            clusters: e.clusters().iter().flatten().map(|c| c.0).collect(),
+            created: e.created(),
+            accessed: e.accessed(),
+            written: e.written(),
+            address: e.address(),
+            children: if kind == "directory" { Some(Vec::new()) } else { None },
+        }
+    }
+}
+
+/// Returns the directory hierarchy of the volume
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+/// * `options` - Filters applied to the entries (the order is always depth-first)
+pub fn tree(fat: &dyn FAT, options: WalkOptions) -> Vec<EntryInfo> {
+    let mut roots: Vec<EntryInfo> = Vec::new();
+    // the entries of the current path, the innermost directory last
+    let mut stack: Vec<EntryInfo> = Vec::new();
+
+    for item in fat.walk(WalkOptions { order: Order::DepthFirst, ..options }) {
+        while stack.len() >= item.depth {
+            attach(&mut stack, &mut roots);
+        }
+        stack.push(EntryInfo::new(&item.path, &item.entry));
+    }
+
+    while !stack.is_empty() {
+        attach(&mut stack, &mut roots);
+    }
+
+    roots
+}
+
+/// Move the innermost entry of the stack into its parent (or the root list)
+fn attach(stack: &mut Vec<EntryInfo>, roots: &mut Vec<EntryInfo>) {
+    if let Some(node) = stack.pop() {
+        match stack.last_mut().and_then(|parent| parent.children.as_mut()) {
+            Some(children) => children.push(node),
+            None => roots.push(node),
+        }
+    }
+}
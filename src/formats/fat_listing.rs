@@ -0,0 +1,140 @@
+use super::fat::*;
+use super::fat_entry::*;
+use super::fat_hash::Hashes;
+use super::fat_reader::FileReader;
+use super::fat_table::cluster_runs;
+use super::fat_walk::{DeletedDirs, WalkOptions};
+use serde::Serialize;
+use std::io::{self, Write};
+
+#[derive(Debug, Serialize)]
+/// A single row of the file listing
+pub struct Row {
+    /// Absolute path of the entry
+    pub path: String,
+    pub long_name: Option<String>,
+    /// Short name in its dotted form (e.g. `README.TXT`)
+    pub short_name: String,
+    /// `file`, `directory` or `volume`
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// Attributes as flags (`RHSVDA`)
+    pub attributes: String,
+    pub deleted: bool,
+    /// File size (in bytes)
+    pub size: u32,
+    pub start_cluster: u32,
+    /// Number of runs of consecutive clusters
+    pub fragments: usize,
+    pub created: Option<Timestamp>,
+    pub accessed: Option<Timestamp>,
+    pub written: Option<Timestamp>,
+    pub md5: Option<String>,
+    pub sha1: Option<String>,
+    pub sha256: Option<String>,
+}
+
+impl Row {
+    /// Returns the row of a single entry
+    ///
+    /// # Arguments
+    ///
+    /// * `fat` - The file system the entry belongs to
+    /// * `path` - Absolute path of the entry
+    /// * `e` - The directory entry
+    /// * `deleted` - Is the entry or one of its ancestors deleted? (see `DeletedDirs`)
+    /// * `hash` - Hash the content of files that aren't deleted
+    pub fn new(fat: &Fat, path: &str, e: &Entry, deleted: bool, hash: bool) -> Row {
+        let kind = if e.is_disk_volume_entry() {
+            "volume"
+        } else if e.is_subdir_entry() {
+            "directory"
+        } else {
+            "file"
+        };
+        let clusters: Vec<u32> = e.clusters().iter().flatten().map(|c| c.0).collect();
+
+        let hashes = if hash && kind == "file" && !deleted {
+            Hashes::compute(&mut FileReader::new(fat, e)).ok()
+        } else {
+            None
+        };
+
+        Row {
+            path: path.to_string(),
+            long_name: e.long_name().map(|n| n.to_string()),
+            short_name: e.dotted_name(),
+            kind,
+            attributes: e.flags(),
+            deleted,
+            size: e.size(),
+            start_cluster: e.start().0,
+            fragments: cluster_runs(&clusters).len(),
+            created: e.created(),
+            accessed: e.accessed(),
+            written: e.written(),
+            md5: hashes.as_ref().map(|h| h.md5.clone()),
+            sha1: hashes.as_ref().map(|h| h.sha1.clone()),
+            sha256: hashes.map(|h| h.sha256),
+        }
+    }
+}
+
+/// Returns one row per entry of the volume (depth-first)
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+/// * `hash` - Hash the content of files that aren't deleted (nor within a deleted directory)
+pub fn listing(fat: &dyn FAT, hash: bool) -> Vec<Row> {
+    let mut deleted = DeletedDirs::default();
+
+    fat.walk(WalkOptions::default())
+        .map(|item| Row::new(fat.fat(), &item.path, &item.entry, deleted.is_deleted(&item), hash))
+        .collect()
+}
+
+/// Write the rows as delimiter separated values with a header line
+///
+/// # Arguments
+///
+/// * `writer` - Destination of the listing
+/// * `rows` - The rows of the listing
+/// * `delimiter` - Field delimiter (e.g. `b','` or `b'\t'`)
+pub fn write<W: Write>(writer: W, rows: &[Row], delimiter: u8) -> io::Result<()> {
+    let mut csv = csv::WriterBuilder::new().delimiter(delimiter).from_writer(writer);
+
+    for row in rows {
+        csv.serialize(row)?;
+    }
+
+    csv.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fat_test::*;
+
+    #[test]
+    fn files_of_deleted_directories_are_deleted_and_not_hashed() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"\xe5IR        ", 0x10, 3, 0), short_entry(b"OTHER   TXT", 0x20, 4, 3)]);
+        image.set_dir(3, 0, &[
+            short_entry(b".          ", 0x10, 3, 0),
+            short_entry(b"..         ", 0x10, 0, 0),
+            short_entry(b"FILE    TXT", 0x20, 4, 3),
+        ]);
+        // the cluster of FILE.TXT has been reused by OTHER.TXT
+        image.set_chain(&[4]);
+        image.write(Image::cluster_offset(4), b"abc");
+
+        let rows = listing(&*image.open(), true);
+        let row = |path: &str| rows.iter().find(|r| r.path == path).unwrap();
+
+        assert!(row("/_IR/FILE.TXT").deleted);
+        assert_eq!(row("/_IR/FILE.TXT").md5, None);
+        assert!(!row("/OTHER.TXT").deleted);
+        assert_eq!(row("/OTHER.TXT").md5.as_deref(), Some("900150983cd24fb0d6963f7d28e17f72"));
+    }
+}
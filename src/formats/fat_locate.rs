@@ -0,0 +1,207 @@
+use super::fat::*;
+use super::fat_recover;
+use super::fat_table::TableEntry;
+use super::fat_walk::WalkOptions;
+use std::{collections::HashMap, fmt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A file or directory whose cluster chain contains a specific cluster
+pub struct Owner {
+    /// Absolute path of the entry (`/` for the FAT32 root directory)
+    pub path: String,
+    /// Is the entry deleted (or within a deleted directory)? [yes/no] (its clusters are presumed)
+    pub deleted: bool,
+    /// Position of the cluster within the chain (0 for the start cluster)
+    pub index: usize,
+}
+
+#[derive(Debug, Default)]
+/// Maps every cluster to the entries whose chain contains it
+///
+/// Chains of live entries are followed through the active FAT. Deleted
+/// entries are mapped to the clusters they presumably occupied, so a cluster
+/// can have several owners (e.g. a deleted file whose clusters have been
+/// allocated again).
+///
+/// # Examples
+///
+/// ```no_run
+/// use greasy::formats::{fat, fat_locate::ClusterIndex};
+/// use memmap::MmapOptions;
+/// use std::fs::File;
+///
+/// let file = File::open("fat-16.dd").unwrap();
+/// let mem = unsafe { MmapOptions::new().map(&file).unwrap() };
+/// let fat = fat::Fat::new(mem);
+///
+/// let index = ClusterIndex::new(&*fat);
+/// if let Some(location) = index.locate(fat.fat(), 0xb000) {
+///     println!("{}", location);
+/// }
+/// ```
+pub struct ClusterIndex {
+    owners: HashMap<u32, Vec<Owner>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The structure a byte of the volume belongs to
+pub enum Location {
+    /// The (primary) boot sector
+    BootSector,
+    /// The FSInfo sector (FAT32)
+    FsInfo,
+    /// The backup boot sector (FAT32)
+    BackupBootSector,
+    /// Any other sector of the reserved area
+    Reserved,
+    /// The entry of `cluster` within FAT table `fat`
+    FatTable {
+        fat: u8,
+        cluster: u32,
+        /// Does the entry lie beyond the last valid cluster (rest of the last FAT sector)? [yes/no]
+        unused: bool,
+    },
+    /// A slot of the fixed size FAT16 root directory
+    RootDir { slot: usize },
+    /// A cluster of the cluster area
+    Cluster {
+        cluster: u32,
+        /// Byte offset within the cluster
+        offset: usize,
+        /// Decoded entry of the cluster in the active FAT (None if it couldn't be read)
+        entry: Option<TableEntry>,
+        owners: Vec<Owner>,
+    },
+    /// Sectors at the end of the cluster area that don't form a whole cluster
+    ClusterAreaTail,
+    /// Beyond the last sector of the volume (but within the image)
+    PastEnd,
+}
+
+impl ClusterIndex {
+    /// Build the index by walking all entries of the volume (including deleted ones)
+    ///
+    /// # Arguments
+    ///
+    /// * `fat` - The file system
+    pub fn new(fat: &dyn FAT) -> ClusterIndex {
+        let base = fat.fat();
+        let mut index = ClusterIndex::default();
+
+        for (i, c) in base.root_clusters().into_iter().enumerate() {
+            index.add(c, Owner { path: "/".to_string(), deleted: false, index: i });
+        }
+
+        for item in fat.walk(WalkOptions::default()) {
+            let unlinked = fat_recover::is_unlinked(base, &item.entry);
+            let clusters: Vec<u32> = if unlinked {
+                fat_recover::presumed_clusters(base, &item.entry).iter().map(|c| c.0).collect()
+            } else {
+                item.entry.clusters().iter().flatten().map(|c| c.0).collect()
+            };
+
+            for (i, c) in clusters.into_iter().enumerate() {
+                index.add(c, Owner { path: item.path.clone(), deleted: unlinked, index: i });
+            }
+        }
+
+        index
+    }
+
+    fn add(&mut self, cluster: u32, owner: Owner) {
+        self.owners.entry(cluster).or_default().push(owner);
+    }
+
+    /// Returns all entries whose chain contains the given cluster
+    pub fn owners(&self, cluster: u32) -> &[Owner] {
+        self.owners.get(&cluster).map(|o| o.as_slice()).unwrap_or(&[])
+    }
+
+    /// Returns the structure the given byte belongs to
+    ///
+    /// Returns None if the offset lies beyond the end of the image.
+    ///
+    /// # Arguments
+    ///
+    /// * `fat` - The file system the index was built for
+    /// * `offset` - Byte offset from the beginning of the volume
+    pub fn locate(&self, fat: &Fat, offset: usize) -> Option<Location> {
+        if offset >= fat.mem().len() {
+            return None;
+        }
+
+        let bps = fat.bytes_per_sector() as usize;
+        let sector = (offset / bps) as u32;
+        let boot = fat.boot_sector();
+
+        let location = if sector >= fat.total_sectors() {
+            Location::PastEnd
+        } else if sector == 0 {
+            Location::BootSector
+        } else if sector < fat.start_fat_area() {
+            if boot.is_fat32() && sector == boot.fs_info_sector as u32 {
+                Location::FsInfo
+            } else if boot.is_fat32() && sector == boot.backup_boot_sector as u32 {
+                Location::BackupBootSector
+            } else {
+                Location::Reserved
+            }
+        } else if sector < fat.start_fat_area() + fat.fat_table_count() as u32 * fat.fat_table_sectors() {
+            let n = (sector - fat.start_fat_area()) / fat.fat_table_sectors();
+            let start = fat.offset(&Sector(fat.start_fat_area() + n * fat.fat_table_sectors()));
+            let cluster = ((offset - start) / (fat.fat_table_entry_size() / 8) as usize) as u32;
+            Location::FatTable { fat: n as u8, cluster, unused: cluster > fat.total_clusters() }
+        } else if sector < fat.start_cluster_area() {
+            Location::RootDir { slot: (offset - fat.offset(&Sector(fat.start_root_dir()))) / 32 }
+        } else {
+            let cluster = fat.sector_to_cluster(&Sector(sector)).0 + 2;
+            if !fat.is_valid_cluster(cluster) {
+                return Some(Location::ClusterAreaTail);
+            }
+
+            Location::Cluster {
+                cluster,
+                offset: offset - fat.clusters_to_offsets(&[Cluster(cluster)])[0],
+                entry: fat.read_table_entry(fat.active_fat(), &Cluster(cluster)).map(|v| TableEntry::new(v, fat.fat_table_entry_size())),
+                owners: self.owners(cluster).to_vec(),
+            }
+        };
+
+        Some(location)
+    }
+}
+
+impl fmt::Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (cluster {} of the chain{})", self.path, self.index, if self.deleted { ", deleted" } else { "" })
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Location::BootSector => write!(f, "boot sector"),
+            Location::FsInfo => write!(f, "FSInfo sector"),
+            Location::BackupBootSector => write!(f, "backup boot sector"),
+            Location::Reserved => write!(f, "reserved area"),
+            Location::FatTable { fat, cluster, unused } => {
+                write!(f, "FAT {}, entry of cluster {}{}", fat, cluster, if *unused { " (beyond the last cluster)" } else { "" })
+            },
+            Location::RootDir { slot } => write!(f, "root directory, slot {}", slot),
+            Location::Cluster { cluster, offset, entry, owners } => {
+                let state = match entry {
+                    Some(TableEntry::Free) => "unallocated",
+                    Some(_) => "allocated",
+                    None => "FAT entry unreadable",
+                };
+                write!(f, "cluster {} ({}, offset {})", cluster, state, offset)?;
+                for owner in owners {
+                    write!(f, "\n|- {}", owner)?;
+                }
+                Ok(())
+            },
+            Location::ClusterAreaTail => write!(f, "unused sectors at the end of the cluster area"),
+            Location::PastEnd => write!(f, "beyond the end of the volume"),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use super::fat::*;
+use super::fat_dirslack;
+use super::fat_entry::*;
+use super::fat_recover;
+use super::fat_walk::{WalkEntry, WalkOptions};
+use std::collections::HashSet;
+
+/// Path of the virtual directory that holds all orphaned directories
+pub const ORPHAN_ROOT: &str = "/$OrphanFiles";
+
+#[derive(Debug, Clone)]
+/// A directory cluster in unallocated space that no entry of the volume points to
+pub struct Orphan {
+    /// The cluster holding the directory
+    pub cluster: u32,
+    /// Start cluster of the parent directory (from the `..` entry, 0 for the root directory)
+    pub parent: u32,
+    /// The `.` entry of the directory (with the cluster attached)
+    pub entry: Entry,
+}
+
+impl Orphan {
+    /// Returns the virtual path of the directory (e.g. `/$OrphanFiles/OrphanDir-16`)
+    pub fn path(&self) -> String {
+        format!("{}/OrphanDir-{}", ORPHAN_ROOT, self.cluster)
+    }
+}
+
+/// Checks if a cluster holds the first cluster of a directory
+///
+/// The cluster must start with a `.` entry pointing to the cluster itself and
+/// a `..` entry, followed by plausible entries up to the end of directory marker.
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+/// * `cluster` - The cluster to check
+pub fn is_dir_cluster(fat: &Fat, cluster: u32) -> bool {
+    let start = fat.clusters_to_offsets(&[Cluster(cluster)])[0];
+    let end = start + fat.cluster_size();
+    if end > fat.mem().len() {
+        return false;
+    }
+
+    let slots: Vec<&[u8]> = fat.mem()[start..end].chunks(32).collect();
+    let this = Entry::new(slots[0]);
+    let prev = Entry::new(slots[1]);
+
+    this.is_this_entry() && this.is_subdir_entry() && this.start().0 == cluster
+        && prev.is_prev_entry() && prev.is_subdir_entry()
+        && slots[2..].iter()
+            .take_while(|raw| raw[0] != 0x00)
+            .all(|raw| fat_dirslack::is_plausible(fat, raw))
+}
+
+/// Returns all orphaned directories of the volume
+///
+/// An orphaned directory is a directory cluster within unallocated space
+/// whose start cluster isn't referenced by any entry of the volume (including
+/// deleted entries and entries within other orphaned directories), e.g.
+/// because the entry of the deleted directory has been overwritten.
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+pub fn orphans(fat: &dyn FAT) -> Vec<Orphan> {
+    let base = fat.fat();
+    let mut referenced: HashSet<u32> = base.root_clusters().into_iter().collect();
+    referenced.extend(fat.walk(WalkOptions::default()).map(|item| item.entry.start().0));
+
+    let mut found: Vec<Orphan> = (2..=base.total_clusters())
+        .filter(|c| !referenced.contains(c) && fat_recover::is_free(base, *c) && is_dir_cluster(base, *c))
+        .map(|c| {
+            let start = base.clusters_to_offsets(&[Cluster(c)])[0];
+            let mut entry = Entry::new(&base.mem()[start..start + 32]);
+            entry.add_address(start);
+            entry.add_clusters(vec![Cluster(c)]);
+            let parent = Entry::new(&base.mem()[start + 32..start + 64]).start().0;
+            Orphan { cluster: c, parent, entry }
+        })
+        .collect();
+
+    // orphans referenced from within another orphan are listed below it
+    let nested: HashSet<u32> = found.iter()
+        .flat_map(|o| base.dir_entries(&o.entry))
+        .filter(|e| !e.is_this_entry() && !e.is_prev_entry())
+        .map(|e| e.start().0)
+        .collect();
+    found.retain(|o| !nested.contains(&o.cluster));
+
+    found
+}
+
+/// Returns all entries of the orphaned directories with their virtual path
+///
+/// Every orphaned directory is listed as `/$OrphanFiles/OrphanDir-<cluster>`
+/// followed by its content (depth-first). Deleted sub directories are
+/// followed as well.
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+pub fn orphan_files(fat: &dyn FAT) -> Vec<WalkEntry> {
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+
+    for orphan in orphans(fat) {
+        visited.insert(orphan.cluster);
+        files.push(WalkEntry { path: orphan.path(), depth: 2, entry: orphan.entry.clone() });
+        collect(fat, &orphan.entry, &orphan.path(), 2, &mut visited, &mut files);
+    }
+
+    files
+}
+
+/// Add the content of a directory (recursively) to `files`
+///
+/// Only sub directories within unallocated space are entered, stale entries
+/// pointing to live directories would list their content twice.
+fn collect(fat: &dyn FAT, dir: &Entry, path: &str, depth: usize, visited: &mut HashSet<u32>, files: &mut Vec<WalkEntry>) {
+    let entries = match fat.read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for e in entries {
+        let item = WalkEntry { path: format!("{}/{}", path, e.name()), depth: depth + 1, entry: e };
+        let start = item.entry.start().0;
+        let descend = item.entry.is_subdir_entry() && !item.entry.is_disk_volume_entry()
+            && fat.fat().is_valid_cluster(start) && fat_recover::is_free(fat.fat(), start) && visited.insert(start);
+        let (entry, child) = (item.entry.clone(), item.path.clone());
+        files.push(item);
+
+        if descend {
+            collect(fat, &entry, &child, depth + 1, visited, files);
+        }
+    }
+}
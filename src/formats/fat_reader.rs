@@ -0,0 +1,141 @@
+use super::fat::*;
+use super::fat_entry::*;
+use std::{
+    cmp,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+#[derive(Debug)]
+/// Streams the content of a file by following its cluster chain
+///
+/// Logical file offsets are mapped onto the clusters of the entry. By default
+/// the reader stops at the end of the file (`Entry.size`); in slack mode it
+/// continues up to the end of the last cluster of the chain.
+///
+/// # Examples
+///
+/// ```no_run
+/// use greasy::formats::{fat, fat_reader::FileReader};
+/// use memmap::MmapOptions;
+/// use std::{fs::File, io::Read};
+///
+/// let file = File::open("fat-16.dd").unwrap();
+/// let mem = unsafe { MmapOptions::new().map(&file).unwrap() };
+/// let fat = fat::Fat::new(mem);
+///
+/// let entry = fat.stat("/Pictures/my dog.jpg").unwrap();
+/// let mut content = Vec::new();
+/// FileReader::new(fat.fat(), &entry).read_to_end(&mut content).unwrap();
+/// ```
+pub struct FileReader<'a> {
+    fat: &'a Fat,
+    /// Byte offsets of all clusters of the file
+    offsets: Vec<usize>,
+    /// Number of readable bytes
+    len: u64,
+    /// Current position within the file
+    pos: u64,
+}
+
+impl<'a> FileReader<'a> {
+    /// Returns a reader over the content of a file
+    ///
+    /// If the cluster chain is shorter than the size of the file, only the
+    /// clusters of the chain can be read.
+    ///
+    /// # Arguments
+    ///
+    /// * `fat` - The file system the entry belongs to
+    /// * `entry` - Directory entry of the file
+    pub fn new(fat: &'a Fat, entry: &Entry) -> FileReader<'a> {
+        let mut reader = FileReader::with_slack(fat, entry);
+        reader.len = cmp::min(reader.len, entry.size() as u64);
+        reader
+    }
+
+    /// Returns a reader over the content of a file including the file slack
+    ///
+    /// The file slack is the unused space between the end of the file and
+    /// the end of its last cluster.
+    ///
+    /// # Arguments
+    ///
+    /// * `fat` - The file system the entry belongs to
+    /// * `entry` - Directory entry of the file
+    pub fn with_slack(fat: &'a Fat, entry: &Entry) -> FileReader<'a> {
+        let offsets = match entry.clusters() {
+            Some(clusters) => fat.clusters_to_offsets(clusters),
+            None => fat.clusters_to_offsets(&fat.get_cluster_chain_in(fat.active_fat(), entry.start())),
+        };
+        let len = (offsets.len() * fat.cluster_size()) as u64;
+
+        FileReader { fat, offsets, len, pos: 0 }
+    }
+
+    /// Returns the number of readable bytes
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Checks if there is nothing to read
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the byte offset within the volume of a logical file offset
+    ///
+    /// # Arguments
+    ///
+    /// * `pos` - Logical offset within the file (must be smaller than len())
+    pub fn volume_offset(&self, pos: u64) -> Option<usize> {
+        if pos >= self.len {
+            return None;
+        }
+
+        let cluster_size = self.fat.cluster_size() as u64;
+        Some(self.offsets[(pos / cluster_size) as usize] + (pos % cluster_size) as usize)
+    }
+}
+
+impl<'a> Read for FileReader<'a> {
+    /// Read from the current position up to the end of the current cluster
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let start = match self.volume_offset(self.pos) {
+            Some(start) => start,
+            None => return Ok(0),
+        };
+
+        let cluster_size = self.fat.cluster_size() as u64;
+        let in_cluster = cluster_size - self.pos % cluster_size;
+        let n = cmp::min(buf.len() as u64, cmp::min(in_cluster, self.len - self.pos)) as usize;
+
+        // clusters beyond the end of the image can't be read
+        let mem = self.fat.mem();
+        if start + n > mem.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("offset 0x{:x} lies outside of the volume", start)));
+        }
+
+        buf[..n].copy_from_slice(&mem[start..start + n]);
+        self.pos += n as u64;
+
+        Ok(n)
+    }
+}
+
+impl<'a> Seek for FileReader<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.len.checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+
+        match pos {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            },
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position")),
+        }
+    }
+}
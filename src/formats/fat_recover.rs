@@ -0,0 +1,302 @@
+use super::fat::*;
+use super::fat_dirslack;
+use super::fat_entry::*;
+use super::fat_orphan;
+use super::fat_table::*;
+use super::fat_walk::WalkEntry;
+use std::{
+    collections::HashSet,
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+/// Checks if a cluster is marked as free in the active FAT
+///
+/// Clusters whose FAT entry can't be read aren't considered free.
+pub fn is_free(fat: &Fat, cluster: u32) -> bool {
+    fat.read_table_entry(fat.active_fat(), &Cluster(cluster)).map(|v| TableEntry::new(v, fat.fat_table_entry_size())) == Some(TableEntry::Free)
+}
+
+/// Checks if the data of an entry isn't linked in the FAT anymore
+///
+/// This is the case for deleted entries and for entries of deleted
+/// directories whose start cluster has been freed.
+pub fn is_unlinked(fat: &Fat, e: &Entry) -> bool {
+    e.is_deleted() || (fat.is_valid_cluster(e.start().0) && is_free(fat, e.start().0))
+}
+
+/// Returns the clusters a deleted entry presumably occupied
+///
+/// Deleting a file clears its chain in the FAT, so the clusters are assumed
+/// to be allocated contiguously from the start cluster. See
+/// `presumed_dir_clusters()` for directories.
+pub fn presumed_clusters(fat: &Fat, e: &Entry) -> Vec<Cluster> {
+    if e.is_subdir_entry() {
+        return presumed_dir_clusters(fat, e);
+    }
+
+    let count = (e.size() as usize).div_ceil(fat.cluster_size());
+
+    (e.start().0..)
+        .take(count)
+        .take_while(|c| fat.is_valid_cluster(*c))
+        .map(Cluster)
+        .collect()
+}
+
+/// Returns the clusters a deleted directory presumably occupied
+///
+/// Directories have no size, so the clusters are assumed to be allocated
+/// contiguously from the start cluster up to the cluster that holds the end
+/// of directory marker. The start cluster must be free and still begin with
+/// the `.` entry of the directory and a `..` entry, otherwise it has been
+/// reused and no clusters are returned. Following clusters must be free and hold plausible
+/// entries only.
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+/// * `e` - Directory entry of the deleted directory
+pub fn presumed_dir_clusters(fat: &Fat, e: &Entry) -> Vec<Cluster> {
+    let start = e.start().0;
+    if !fat.is_valid_cluster(start) || !is_free(fat, start) || !fat_orphan::is_dir_cluster(fat, start) {
+        return Vec::new();
+    }
+
+    let mut clusters = vec![Cluster(start)];
+    let mut c = start;
+
+    while !has_end_marker(fat, c) {
+        c += 1;
+        if !fat.is_valid_cluster(c) || !is_free(fat, c) || !is_dir_continuation(fat, c) {
+            break;
+        }
+        clusters.push(Cluster(c));
+    }
+
+    clusters
+}
+
+/// Returns the slots of a cluster (empty if it lies outside of the image)
+fn slots(fat: &Fat, cluster: u32) -> Vec<&[u8]> {
+    let start = fat.clusters_to_offsets(&[Cluster(cluster)])[0];
+    fat.mem().get(start..start + fat.cluster_size()).map(|c| c.chunks(32).collect()).unwrap_or_default()
+}
+
+/// Checks if a cluster contains the end of directory marker
+fn has_end_marker(fat: &Fat, cluster: u32) -> bool {
+    slots(fat, cluster).iter().any(|raw| raw[0] == 0x00)
+}
+
+/// Checks if a cluster looks like the continuation of a directory
+fn is_dir_continuation(fat: &Fat, cluster: u32) -> bool {
+    let slots = slots(fat, cluster);
+
+    slots.first().is_some_and(|raw| raw[0] != 0x00)
+        && slots.iter().take_while(|raw| raw[0] != 0x00).all(|raw| fat_dirslack::is_plausible(fat, raw))
+}
+
+/// Returns a copy of an unlinked entry with its presumed clusters
+///
+/// Returns None if the entry has no data or if any of the presumed clusters
+/// has been allocated again (the data is probably overwritten).
+pub fn recover(fat: &Fat, e: &Entry) -> Option<Entry> {
+    let clusters = presumed_clusters(fat, e);
+
+    if clusters.is_empty() || clusters.iter().any(|c| !is_free(fat, c.0)) {
+        return None;
+    }
+
+    let mut recovered = e.clone();
+    recovered.add_clusters(clusters);
+    Some(recovered)
+}
+
+/// Returns the path a recovered entry is written to within an output directory
+///
+/// Names on the volume aren't trusted: every component of `path` must be a
+/// plain name (no `.`, `..`, root or drive prefix) without a backslash, and
+/// the name of the entry itself must not contain a path separator. The
+/// result therefore always lies within `dir`.
+///
+/// # Arguments
+///
+/// * `dir` - The output directory
+/// * `path` - Path of the entry relative to the output directory (separated by `/`)
+/// * `name` - Name of the entry
+pub fn output_path(dir: &Path, path: &str, name: &str) -> io::Result<PathBuf> {
+    let unsafe_path = || io::Error::new(io::ErrorKind::InvalidData, format!("{}: unsafe file name", path));
+
+    if name.contains(['/', '\\']) {
+        return Err(unsafe_path());
+    }
+
+    let mut target = dir.to_path_buf();
+    for part in path.trim_start_matches('/').split('/') {
+        let mut components = Path::new(part).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(c)), None) if !part.contains('\\') && c == part => target.push(c),
+            _ => return Err(unsafe_path()),
+        }
+    }
+
+    Ok(target)
+}
+
+/// Returns all entries below a (deleted) directory with their path
+///
+/// Sub directories are followed depth-first, deleted ones using their
+/// presumed clusters. Each directory is entered only once.
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+/// * `dir` - The directory entry
+/// * `path` - Absolute path of the directory
+pub fn subtree(fat: &Fat, dir: &Entry, path: &str) -> Vec<WalkEntry> {
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    visited.insert(dir.start().0);
+    collect(fat, dir, path, 0, &mut visited, &mut files);
+    files
+}
+
+/// Add the content of a directory (recursively) to `files`
+fn collect(fat: &Fat, dir: &Entry, path: &str, depth: usize, visited: &mut HashSet<u32>, files: &mut Vec<WalkEntry>) {
+    for e in fat.dir_entries(dir).into_iter().filter(|e| !e.is_this_entry() && !e.is_prev_entry()) {
+        let item = WalkEntry { path: format!("{}/{}", path.trim_end_matches('/'), e.name()), depth: depth + 1, entry: e };
+        let descend = item.entry.is_subdir_entry() && !item.entry.is_disk_volume_entry() && visited.insert(item.entry.start().0);
+        let (entry, child) = (item.entry.clone(), item.path.clone());
+        files.push(item);
+
+        if descend {
+            collect(fat, &entry, &child, depth + 1, visited, files);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fat_test::*;
+
+    /// Write a directory cluster with `.` and `..` followed by the given entries
+    fn dir(image: &mut Image, cluster: u32, parent: u32, entries: &[[u8; 32]]) {
+        image.set_dir(cluster, 0, &[short_entry(b".          ", 0x10, cluster, 0), short_entry(b"..         ", 0x10, parent, 0)]);
+        image.set_dir(cluster, 2, entries);
+    }
+
+    fn numbers(clusters: Vec<Cluster>) -> Vec<u32> {
+        clusters.iter().map(|c| c.0).collect()
+    }
+
+    /// Returns the first entry of the root directory
+    fn first(fat: &dyn FAT) -> Entry {
+        fat.root_dir().next().unwrap()
+    }
+
+    #[test]
+    fn deleted_directory_spans_contiguous_clusters() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"\xe5IR        ", 0x10, 3, 0)]);
+        // 30 entries fill the first cluster, the second one holds the end marker
+        let files: Vec<[u8; 32]> = (0..32).map(|i| {
+            let mut name = *b"FILE0000TXT";
+            name[4..8].copy_from_slice(format!("{:04}", i).as_bytes());
+            short_entry(&name, 0x20, 0, 0)
+        }).collect();
+        dir(&mut image, 3, 0, &files[..30]);
+        image.set_dir(4, 0, &files[30..]);
+
+        let fat = image.open();
+        let e = first(&*fat);
+
+        assert_eq!(numbers(presumed_dir_clusters(fat.fat(), &e)), vec![3, 4]);
+        assert_eq!(subtree(fat.fat(), &e, "/_IR").len(), 32);
+
+        // the second cluster has been allocated again
+        image.set_chain(&[4]);
+        let fat = image.open();
+        assert_eq!(numbers(presumed_dir_clusters(fat.fat(), &first(&*fat))), vec![3]);
+    }
+
+    #[test]
+    fn deleted_directory_with_reused_start_cluster_is_empty() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"\xe5IR        ", 0x10, 3, 0)]);
+        image.set_chain(&[3]);
+        image.write(Image::cluster_offset(3), b"new content of a file");
+
+        let fat = image.open();
+        let e = first(&*fat);
+
+        assert!(presumed_dir_clusters(fat.fat(), &e).is_empty());
+        assert!(subtree(fat.fat(), &e, "/_IR").is_empty());
+        assert!(recover(fat.fat(), &e).is_none());
+    }
+
+    #[test]
+    fn deleted_directory_with_start_cluster_of_live_directory_is_empty() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"\xe5IR        ", 0x10, 3, 0), short_entry(b"LIVE       ", 0x10, 3, 0)]);
+        image.set_chain(&[3]);
+        dir(&mut image, 3, 0, &[short_entry(b"FILE    TXT", 0x20, 0, 0)]);
+
+        let fat = image.open();
+        let e = first(&*fat);
+
+        assert!(presumed_dir_clusters(fat.fat(), &e).is_empty());
+        assert!(subtree(fat.fat(), &e, "/_IR").is_empty());
+    }
+
+    #[test]
+    fn subtree_terminates_on_cycles() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"\xe5IR        ", 0x10, 3, 0)]);
+        dir(&mut image, 3, 0, &[short_entry(b"\xe5UB        ", 0x10, 4, 0)]);
+        // a sub directory of SUB that points back to DIR
+        dir(&mut image, 4, 3, &[short_entry(b"\xe5OOP       ", 0x10, 3, 0)]);
+
+        let fat = image.open();
+        let paths: Vec<String> = subtree(fat.fat(), &first(&*fat), "/_IR").into_iter().map(|w| w.path).collect();
+
+        assert_eq!(paths, vec!["/_IR/_UB", "/_IR/_UB/_OOP"]);
+    }
+
+    #[test]
+    fn hostile_long_names_are_rejected() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"\xe5IR        ", 0x10, 3, 0)]);
+        let entries = [
+            named_entry("../../evil.txt", b"EVIL    TXT", 0x20, 0, 0, false),
+            named_entry("..", b"DOTS       ", 0x20, 0, 0, false),
+            named_entry("a\\..\\evil.txt", b"EVIL2   TXT", 0x20, 0, 0, false),
+            named_entry("fine.txt", b"FINE    TXT", 0x20, 0, 0, false),
+        ].concat();
+        dir(&mut image, 3, 0, &entries);
+
+        let fat = image.open();
+        let out = Path::new("out");
+        let targets: Vec<(String, bool)> = subtree(fat.fat(), &first(&*fat), "/_IR").into_iter()
+            .map(|w| (w.entry.name(), output_path(out, &w.path, &w.entry.name()).is_ok()))
+            .collect();
+
+        assert_eq!(targets, vec![
+            ("../../evil.txt".to_string(), false),
+            ("..".to_string(), false),
+            ("a\\..\\evil.txt".to_string(), false),
+            ("fine.txt".to_string(), true),
+        ]);
+    }
+
+    #[test]
+    fn output_path_stays_within_the_output_directory() {
+        let dir = Path::new("out");
+
+        assert_eq!(output_path(dir, "/a/b.txt", "b.txt").unwrap(), Path::new("out/a/b.txt"));
+        for path in ["../../x", "/a/../../x", "a/./x", "a//x", "a\\..\\x", ""] {
+            assert!(output_path(dir, path, "x").is_err(), "{}", path);
+        }
+        assert!(output_path(dir, "/a/b/c", "b/c").is_err());
+    }
+}
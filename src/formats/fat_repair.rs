@@ -0,0 +1,695 @@
+use super::fat::*;
+use super::fat_entry::*;
+use super::fat_table::*;
+use super::fat_check::{self, Finding, Problem};
+use byteorder::{ByteOrder, LittleEndian};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::File,
+    io::{self, Seek, SeekFrom, Write},
+};
+
+#[derive(Debug, Clone)]
+/// Selects which kinds of corruption the repair engine fixes
+pub struct RepairOptions {
+    /// Copy the active FAT to all other FAT tables
+    pub sync_fats: bool,
+    /// Terminate broken chains and truncate chains that are longer than the file
+    pub fix_chains: bool,
+    /// Set the size of files whose chain is shorter than their size
+    pub fix_sizes: bool,
+    /// Free lost chains (ignored if `save_lost` is set)
+    pub free_lost: bool,
+    /// Save lost chains as FOUND.000/FILE0000.CHK, FOUND.000/FILE0001.CHK, ...
+    pub save_lost: bool,
+    /// Mark the volume as cleanly unmounted
+    pub clear_dirty: bool,
+}
+
+#[derive(Debug, Clone)]
+/// A single modification of the volume
+pub struct Change {
+    /// Byte offset within the volume
+    pub offset: usize,
+    /// Bytes before the modification
+    pub old: Vec<u8>,
+    /// Bytes after the modification
+    pub new: Vec<u8>,
+    /// Why the bytes are modified
+    pub reason: String,
+}
+
+#[derive(Debug)]
+/// The result of planning a repair
+pub struct Repair {
+    /// All modifications in the order they were planned
+    pub changes: Vec<Change>,
+    /// Findings that can't be repaired automatically
+    pub skipped: Vec<Finding>,
+}
+
+/// Collects modifications without touching the volume
+///
+/// Every write is recorded in an overlay, so later reads see the result of
+/// earlier modifications.
+struct Planner<'a> {
+    fat: &'a Fat,
+    overlay: BTreeMap<usize, u8>,
+    changes: Vec<Change>,
+    /// Number of live entries whose chain contains a cluster
+    owners: HashMap<u32, usize>,
+}
+
+impl Default for RepairOptions {
+    fn default() -> RepairOptions {
+        RepairOptions {
+            sync_fats: true,
+            fix_chains: true,
+            fix_sizes: true,
+            free_lost: true,
+            save_lost: false,
+            clear_dirty: true,
+        }
+    }
+}
+
+impl<'a> Planner<'a> {
+    fn new(fat: &'a Fat, files: &[(String, Entry)]) -> Planner<'a> {
+        let mut planner = Planner { fat, overlay: BTreeMap::new(), changes: Vec::new(), owners: HashMap::new() };
+
+        for (_, e) in files.iter().filter(|(_, e)| !e.is_deleted()) {
+            for c in planner.chain(e.start().0) {
+                *planner.owners.entry(c).or_default() += 1;
+            }
+        }
+
+        planner
+    }
+
+    /// Read bytes as they would look after all planned modifications
+    fn read(&self, offset: usize, len: usize) -> Vec<u8> {
+        (offset..offset + len).map(|o| *self.overlay.get(&o).unwrap_or(&self.fat.mem()[o])).collect()
+    }
+
+    /// Plan a modification (nothing is recorded if the bytes don't change)
+    fn write(&mut self, offset: usize, new: &[u8], reason: &str) {
+        let old = self.read(offset, new.len());
+        if old == new {
+            return;
+        }
+
+        for (i, b) in new.iter().enumerate() {
+            self.overlay.insert(offset + i, *b);
+        }
+        self.changes.push(Change { offset, old, new: new.to_vec(), reason: reason.to_string() });
+    }
+
+    fn entry_size(&self) -> usize {
+        (self.fat.fat_table_entry_size() / 8) as usize
+    }
+
+    /// Read a FAT entry of the given FAT table
+    ///
+    /// Returns None if the entry lies beyond the end of the image.
+    fn table_entry(&self, fat: u8, cluster: u32) -> Option<u32> {
+        let offset = self.fat.fat_table_offset_in(fat, &Cluster(cluster));
+        if offset + self.entry_size() > self.fat.mem().len() {
+            return None;
+        }
+
+        let bytes = self.read(offset, self.entry_size());
+        Some(if bytes.len() == 2 { LittleEndian::read_u16(&bytes) as u32 } else { LittleEndian::read_u32(&bytes) })
+    }
+
+    /// Decode a FAT entry of the active FAT table
+    fn decoded_entry(&self, cluster: u32) -> Option<TableEntry> {
+        self.table_entry(self.fat.active_fat(), cluster).map(|v| TableEntry::new(v, self.fat.fat_table_entry_size()))
+    }
+
+    /// Write a FAT entry of the active FAT table
+    ///
+    /// The upper 4 bits of FAT32 entries are reserved and preserved. Entries
+    /// beyond the end of the image are left alone.
+    fn set_table_entry(&mut self, cluster: u32, value: u32, reason: &str) {
+        let fat = self.fat.active_fat();
+        let offset = self.fat.fat_table_offset_in(fat, &Cluster(cluster));
+        let old = match self.table_entry(fat, cluster) {
+            Some(old) => old,
+            None => return,
+        };
+        let mut bytes = vec![0; self.entry_size()];
+
+        if bytes.len() == 2 {
+            LittleEndian::write_u16(&mut bytes, value as u16);
+        } else {
+            let reserved = old & 0xf000_0000;
+            LittleEndian::write_u32(&mut bytes, reserved | (value & 0x0fff_ffff));
+        }
+
+        self.write(offset, &bytes, reason);
+    }
+
+    fn eof(&self) -> u32 {
+        if self.entry_size() == 2 { 0xffff } else { 0x0fff_ffff }
+    }
+
+    /// Follow a chain through the active FAT (including planned modifications)
+    fn chain(&self, start: u32) -> Vec<u32> {
+        let mut clusters = Vec::new();
+        let mut seen = HashSet::new();
+        let mut n = start;
+
+        while self.fat.is_valid_cluster(n) && seen.insert(n) {
+            clusters.push(n);
+            n = match self.decoded_entry(n) {
+                Some(TableEntry::Next(next)) => next,
+                _ => break,
+            };
+        }
+
+        clusters
+    }
+
+    /// Returns the first free cluster that isn't part of `exclude`
+    fn free_cluster(&self, exclude: &HashSet<u32>) -> Option<u32> {
+        (2..=self.fat.total_clusters()).find(|c| !exclude.contains(c) && self.decoded_entry(*c) == Some(TableEntry::Free))
+    }
+
+    /// Terminate a chain that ends in a free, bad or invalid cluster or loops back
+    fn fix_chain(&mut self, path: &str, e: &Entry, problem: Problem) {
+        let chain = self.chain(e.start().0);
+        let last = match chain.last() {
+            Some(last) => *last,
+            None => return,
+        };
+
+        if problem == Problem::BadCluster {
+            // the last cluster is bad, terminate the chain right before it
+            match chain.len() {
+                1 => self.clear_start(path, e),
+                n => self.set_table_entry(chain[n - 2], self.eof(), &format!("terminate chain of {} before bad cluster {}", path, last)),
+            }
+        } else {
+            self.set_table_entry(last, self.eof(), &format!("terminate chain of {} at cluster {}", path, last));
+        }
+    }
+
+    /// Remove the start cluster and the size of an entry
+    fn clear_start(&mut self, path: &str, e: &Entry) {
+        if let Some(address) = e.address() {
+            self.write(address + 20, &[0, 0], &format!("clear start cluster of {}", path));
+            self.write(address + 26, &[0, 0], &format!("clear start cluster of {}", path));
+            self.write(address + 28, &[0, 0, 0, 0], &format!("clear size of {}", path));
+        }
+    }
+
+    /// Make the chain length and the size of a file agree
+    ///
+    /// Chains longer than required by the size are truncated, files whose chain
+    /// is too short get the size of their chain.
+    fn fix_size(&mut self, path: &str, e: &Entry, options: &RepairOptions) {
+        let chain = self.chain(e.start().0);
+        let expected = (e.size() as usize).div_ceil(self.fat.cluster_size());
+
+        if chain.len() > expected && options.fix_chains {
+            if expected == 0 {
+                self.clear_start(path, e);
+            } else {
+                self.set_table_entry(chain[expected - 1], self.eof(), &format!("truncate chain of {} to {} cluster(s)", path, expected));
+            }
+            // clusters that are cross-linked with other entries stay allocated
+            for c in &chain[expected..] {
+                if self.owners.get(c).cloned().unwrap_or(0) <= 1 {
+                    self.set_table_entry(*c, 0, &format!("free cluster beyond the end of {}", path));
+                }
+            }
+        } else if chain.len() < expected && options.fix_sizes {
+            if let Some(address) = e.address() {
+                let mut bytes = [0; 4];
+                LittleEndian::write_u32(&mut bytes, (chain.len() * self.fat.cluster_size()) as u32);
+                self.write(address + 28, &bytes, &format!("set size of {} to its {} cluster(s)", path, chain.len()));
+            }
+        }
+    }
+
+    /// Free all clusters of lost chains
+    fn free_lost(&mut self, lost: &[Vec<u32>]) {
+        for chain in lost {
+            for c in chain {
+                self.set_table_entry(*c, 0, &format!("free lost cluster {}", c));
+            }
+        }
+    }
+
+    /// Save lost chains as files of a new FOUND.nnn directory in the root directory
+    ///
+    /// Lost chains end right before the first cluster that belongs to another
+    /// chain or repeats, so their last cluster gets an end of chain marker.
+    /// Returns false if there is no space left to create the directory.
+    fn save_lost(&mut self, lost: &[Vec<u32>], files: &[(String, Entry)], root: &[usize], root_size: usize) -> bool {
+        let entry_size = 32;
+
+        // pick the first unused FOUND.nnn name
+        let number = (0..1000).find(|n| {
+            let name = format!("FOUND   {:03}", n);
+            !files.iter().any(|(p, e)| !e.is_deleted() && p.matches('/').count() == 1 && e.short_name() == name)
+        });
+        let name = match number {
+            Some(n) => format!("FOUND   {:03}", n),
+            None => return false,
+        };
+
+        // free slot within the root directory
+        let mut slot = None;
+        'outer: for off in root {
+            for i in (0..root_size).step_by(entry_size) {
+                let first = self.read(off + i, 1)[0];
+                if first == 0x00 || first == 0xe5 {
+                    slot = Some((off + i, first == 0x00 && i + entry_size < root_size));
+                    break 'outer;
+                }
+            }
+        }
+        let (slot, terminate) = match slot {
+            Some(s) => s,
+            None => return false,
+        };
+
+        // allocate the clusters of the new directory
+        let needed = ((lost.len() + 2) * entry_size).div_ceil(self.fat.cluster_size());
+        let mut exclude: HashSet<u32> = lost.iter().flatten().cloned().collect();
+        let mut dir = Vec::new();
+        for _ in 0..needed {
+            match self.free_cluster(&exclude) {
+                Some(c) => {
+                    exclude.insert(c);
+                    dir.push(c);
+                },
+                None => return false,
+            }
+        }
+
+        for chain in lost {
+            if let Some(last) = chain.last() {
+                if self.decoded_entry(*last) != Some(TableEntry::EndOfChain) {
+                    self.set_table_entry(*last, self.eof(), &format!("terminate lost chain at cluster {}", last));
+                }
+            }
+        }
+
+        let reason = format!("create directory {}", name);
+        for (i, c) in dir.iter().enumerate() {
+            let next = if i + 1 < dir.len() { dir[i + 1] } else { self.eof() };
+            self.set_table_entry(*c, next, &reason);
+        }
+
+        // directory content: '.', '..' and one entry per lost chain
+        let mut content = vec![0; needed * self.fat.cluster_size()];
+        content[..32].copy_from_slice(&dir_entry(".          ", 0x10, dir[0], 0));
+        content[32..64].copy_from_slice(&dir_entry("..         ", 0x10, 0, 0));
+        for (i, chain) in lost.iter().enumerate() {
+            let size = (chain.len() * self.fat.cluster_size()) as u32;
+            let entry = dir_entry(&format!("FILE{:04}CHK", i), 0x20, chain[0], size);
+            content[(i + 2) * 32..(i + 3) * 32].copy_from_slice(&entry);
+        }
+
+        let cluster_size = self.fat.cluster_size();
+        let clusters: Vec<Cluster> = dir.iter().map(|c| Cluster(*c)).collect();
+        for (i, off) in self.fat.clusters_to_offsets(&clusters).into_iter().enumerate() {
+            self.write(off, &content[i * cluster_size..(i + 1) * cluster_size], &format!("write directory {}", name));
+        }
+
+        if terminate && self.read(slot + entry_size, 1)[0] != 0 {
+            self.write(slot + entry_size, &[0], "keep end of root directory marker");
+        }
+        self.write(slot, &dir_entry(&name, 0x10, dir[0], 0), &format!("add {} to the root directory", name));
+
+        true
+    }
+
+    /// Copy the active FAT to all other FAT tables
+    ///
+    /// One modification is planned per differing sector, covering the bytes
+    /// from the first to the last difference within that sector.
+    fn sync_fats(&mut self) {
+        let active = self.fat.active_fat();
+        let bps = self.fat.bytes_per_sector() as usize;
+        let src = self.fat.fat_table_offset_in(active, &Cluster(0));
+        let len = self.fat.fat_table_offset_in(active, &Cluster(self.fat.total_clusters() + 1)) - src;
+
+        for fat in (0..self.fat.fat_table_count()).filter(|f| *f != active) {
+            let dst = self.fat.fat_table_offset_in(fat, &Cluster(0));
+            let reason = format!("synchronise FAT {} with FAT {}", fat, active);
+
+            for start in (0..len).step_by(bps) {
+                let n = bps.min(len - start);
+                if src.max(dst) + start + n > self.fat.mem().len() {
+                    break;
+                }
+
+                let (new, old) = (self.read(src + start, n), self.read(dst + start, n));
+                let first = (0..n).find(|i| new[*i] != old[*i]);
+                let last = (0..n).rfind(|i| new[*i] != old[*i]);
+                if let (Some(first), Some(last)) = (first, last) {
+                    self.write(dst + start + first, &new[first..=last], &reason);
+                }
+            }
+        }
+    }
+
+    /// Set the clean shutdown bit in the active FAT
+    fn clear_dirty(&mut self) {
+        let offset = self.fat.fat_table_offset_in(self.fat.active_fat(), &Cluster(1));
+        let mut bytes = self.read(offset, self.entry_size());
+
+        if bytes.len() == 2 {
+            bytes[1] |= 0x80;
+        } else {
+            bytes[3] |= 0x08;
+        }
+
+        self.write(offset, &bytes, "clear dirty bit");
+    }
+}
+
+/// Build a 32 byte short directory entry
+fn dir_entry(name: &str, attributes: u8, start: u32, size: u32) -> [u8; 32] {
+    let mut e = [0; 32];
+    e[..11].copy_from_slice(&name.as_bytes()[..11]);
+    e[11] = attributes;
+    LittleEndian::write_u16(&mut e[20..22], (start >> 16) as u16);
+    LittleEndian::write_u16(&mut e[26..28], start as u16);
+    LittleEndian::write_u32(&mut e[28..32], size);
+    e
+}
+
+/// Plan the repair of all problems found by the consistency check
+///
+/// Nothing is written to the volume, the returned changes can be applied
+/// with `apply()`.
+///
+/// # Arguments
+///
+/// * `fat` - The file system to repair
+/// * `files` - All (path, entry) pairs of the volume
+/// * `root` - Byte offsets of the clusters of the root directory
+/// * `root_size` - Maximum number of bytes per cluster of the root directory
+/// * `options` - Selects which problems are repaired
+pub fn plan(fat: &Fat, files: &[(String, Entry)], root: &[usize], root_size: usize, options: &RepairOptions) -> Repair {
+    let findings = fat_check::check(fat, files);
+    let mut planner = Planner::new(fat, files);
+    let mut skipped = Vec::new();
+    let mut lost = Vec::new();
+    let entry = |path: &str| files.iter().find(|(p, e)| p == path && !e.is_deleted()).map(|(_, e)| e);
+
+    for f in &findings {
+        let e = f.path.as_deref().and_then(entry);
+
+        match (f.problem, e) {
+            (Problem::BrokenChain, Some(e)) | (Problem::BadCluster, Some(e)) | (Problem::ChainCycle, Some(e)) if options.fix_chains => {
+                planner.fix_chain(f.path.as_deref().unwrap(), e, f.problem);
+            },
+            (Problem::InvalidStartCluster, Some(e)) if options.fix_chains => {
+                planner.clear_start(f.path.as_deref().unwrap(), e);
+            },
+            (Problem::LostChain, _) if options.save_lost || options.free_lost => lost.push(f.clusters.clone()),
+            (Problem::Dirty, _) if options.clear_dirty => (),
+            (Problem::SizeMismatch, Some(_)) if options.fix_chains || options.fix_sizes => (),
+            _ => skipped.push(f.clone()),
+        }
+    }
+
+    // sizes are fixed after the chains, because terminating a chain may change its length
+    for f in findings.iter().filter(|f| f.problem == Problem::SizeMismatch) {
+        if let Some(e) = f.path.as_deref().and_then(entry) {
+            planner.fix_size(f.path.as_deref().unwrap(), e, options);
+        }
+    }
+
+    if !lost.is_empty() {
+        if options.save_lost {
+            if !planner.save_lost(&lost, files, root, root_size) {
+                skipped.extend(findings.iter().filter(|f| f.problem == Problem::LostChain).cloned());
+            }
+        } else {
+            planner.free_lost(&lost);
+        }
+    }
+
+    if options.clear_dirty && fat.is_dirty() {
+        planner.clear_dirty();
+    }
+
+    if options.sync_fats {
+        planner.sync_fats();
+    }
+
+    Repair { changes: planner.changes, skipped }
+}
+
+/// Write all changes to the volume
+///
+/// # Arguments
+///
+/// * `file` - The volume opened for writing
+/// * `changes` - The changes to apply
+pub fn apply(file: &mut File, changes: &[Change]) -> io::Result<()> {
+    for change in changes {
+        file.seek(SeekFrom::Start(change.offset as u64))?;
+        file.write_all(&change.new)?;
+    }
+
+    file.sync_all()
+}
+
+/// Write a change log with one line per modified sector
+///
+/// All changes of a sector are merged into a single line that lists the
+/// reasons of every change. The bytes are only shown if the modified bytes of
+/// the sector are contiguous and at most 16 bytes long.
+///
+/// # Arguments
+///
+/// * `out` - Where to write the log to
+/// * `bytes_per_sector` - Number of bytes per sector of the volume
+/// * `changes` - The changes to log
+pub fn write_log(out: &mut dyn Write, bytes_per_sector: u16, changes: &[Change]) -> io::Result<()> {
+    let bps = bytes_per_sector as usize;
+    let hex = |b: &[u8]| b.iter().map(|x| format!("{:02x}", x)).collect::<String>();
+
+    // first old and last new value of every modified byte and the reasons per sector
+    let mut bytes: BTreeMap<usize, (u8, u8)> = BTreeMap::new();
+    let mut sectors: BTreeMap<usize, Vec<&str>> = BTreeMap::new();
+    for change in changes {
+        for (i, (old, new)) in change.old.iter().zip(&change.new).enumerate() {
+            let offset = change.offset + i;
+            let reasons = sectors.entry(offset / bps).or_default();
+
+            bytes.entry(offset).or_insert((*old, *new)).1 = *new;
+            if !reasons.contains(&change.reason.as_str()) {
+                reasons.push(&change.reason);
+            }
+        }
+    }
+
+    for (sector, reasons) in sectors {
+        let bytes: Vec<(usize, u8, u8)> = bytes.range(sector * bps..(sector + 1) * bps)
+            .filter(|(_, (o, n))| o != n)
+            .map(|(i, (o, n))| (*i, *o, *n))
+            .collect();
+        let (first, last) = match (bytes.first(), bytes.last()) {
+            (Some(first), Some(last)) => (first.0, last.0),
+            _ => continue,
+        };
+        let reasons = reasons.join(", ");
+
+        if last - first + 1 == bytes.len() && bytes.len() <= 16 {
+            let old: Vec<u8> = bytes.iter().map(|b| b.1).collect();
+            let new: Vec<u8> = bytes.iter().map(|b| b.2).collect();
+            writeln!(out, "sector {}\toffset 0x{:x}\t{} -> {}\t{}", sector, first, hex(&old), hex(&new), reasons)?;
+        } else {
+            writeln!(out, "sector {}\toffset 0x{:x}\t{} byte(s)\t{}", sector, first, bytes.len(), reasons)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fat_test::*;
+
+    const FAT0: usize = 4 * BYTES_PER_SECTOR;
+    const FAT1: usize = 6 * BYTES_PER_SECTOR;
+
+    fn only(sync_fats: bool) -> RepairOptions {
+        RepairOptions { sync_fats, ..RepairOptions::default() }
+    }
+
+    fn applied(image: &Image, changes: &[Change]) -> Image {
+        let mut image = Image { bytes: image.bytes.clone() };
+        for change in changes {
+            image.write(change.offset, &change.new);
+        }
+        image
+    }
+
+    #[test]
+    fn terminates_broken_chain() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"BROKEN  TXT", 0x20, 2, 2048)]);
+        image.set_fat(2, 3);
+
+        let repair = image.open().repair(&only(false));
+
+        assert_eq!(repair.changes.len(), 1);
+        assert_eq!(repair.changes[0].offset, FAT0 + 3 * 2);
+        assert_eq!(repair.changes[0].old, vec![0, 0]);
+        assert_eq!(repair.changes[0].new, vec![0xff, 0xff]);
+        assert!(repair.changes[0].reason.starts_with("terminate chain of"));
+        assert!(applied(&image, &repair.changes).open().check().is_empty());
+    }
+
+    #[test]
+    fn saves_lost_chain() {
+        let mut image = Image::new();
+        image.set_chain(&[5, 6]);
+
+        let options = RepairOptions { save_lost: true, ..only(false) };
+        let repair = image.open().repair(&options);
+        let found = short_entry(b"FOUND   000", 0x10, 2, 0);
+
+        assert!(repair.skipped.is_empty());
+        assert!(repair.changes.iter().any(|c| c.offset == Image::root_offset(0) && c.new == found));
+        assert!(repair.changes.iter().any(|c| c.offset == FAT0 + 2 * 2 && c.new == vec![0xff, 0xff]));
+
+        let fat = applied(&image, &repair.changes).open();
+        let files: Vec<(String, u32, u32)> = fat.walk(Default::default())
+            .map(|w| (w.path.clone(), w.entry.start().0, w.entry.size()))
+            .collect();
+        assert!(files.contains(&("/FOUND.000/FILE0000.CHK".to_string(), 5, 2048)));
+        assert!(fat.check().is_empty());
+    }
+
+    #[test]
+    fn terminates_saved_chains_that_loop_or_run_into_other_chains() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"FILE    TXT", 0x20, 2, 2048)]);
+        image.set_chain(&[2, 3]);
+        // a lost chain that runs into FILE.TXT and a lost cycle
+        image.set_fat(5, 3);
+        image.set_fat(7, 8);
+        image.set_fat(8, 7);
+
+        let options = RepairOptions { save_lost: true, ..only(false) };
+        let repair = image.open().repair(&options);
+
+        assert!(repair.skipped.is_empty());
+        assert!(repair.changes.iter().any(|c| c.offset == FAT0 + 5 * 2 && c.new == vec![0xff, 0xff]));
+        assert!(repair.changes.iter().any(|c| c.offset == FAT0 + 8 * 2 && c.new == vec![0xff, 0xff]));
+
+        let fat = applied(&image, &repair.changes).open();
+        let files: Vec<(String, u32, u32)> = fat.walk(Default::default())
+            .map(|w| (w.path.clone(), w.entry.start().0, w.entry.size()))
+            .collect();
+        assert!(files.contains(&("/FOUND.000/FILE0000.CHK".to_string(), 5, 1024)));
+        assert!(files.contains(&("/FOUND.000/FILE0001.CHK".to_string(), 7, 2048)));
+        assert!(fat.check().is_empty());
+    }
+
+    #[test]
+    fn fixes_size_of_short_chain() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"SHORT   TXT", 0x20, 2, 5000)]);
+        image.set_chain(&[2]);
+
+        let repair = image.open().repair(&only(false));
+
+        assert_eq!(repair.changes.len(), 1);
+        assert_eq!(repair.changes[0].offset, Image::root_offset(0) + 28);
+        assert_eq!(repair.changes[0].new, 1024u32.to_le_bytes().to_vec());
+    }
+
+    #[test]
+    fn plans_one_change_per_differing_sector_to_sync_fats() {
+        let mut image = Image::new();
+        for c in [10, 11, 12, 400].iter() {
+            image.set_fat_in(1, *c, 0xfff7);
+        }
+
+        let repair = image.open().repair(&only(true));
+
+        assert_eq!(repair.changes.len(), 2);
+        assert_eq!((repair.changes[0].offset, repair.changes[0].new.len()), (FAT1 + 10 * 2, 6));
+        assert_eq!((repair.changes[1].offset, repair.changes[1].new.len()), (FAT1 + 400 * 2, 2));
+        assert!(repair.changes.iter().all(|c| c.reason == "synchronise FAT 1 with FAT 0"));
+    }
+
+    #[test]
+    fn clears_dirty_bit() {
+        let mut image = Image::new();
+        image.set_fat(1, 0x7fff);
+
+        let repair = image.open().repair(&only(true));
+
+        assert_eq!(repair.changes.len(), 2);
+        assert_eq!((repair.changes[0].offset, &repair.changes[0].reason[..]), (FAT0 + 2, "clear dirty bit"));
+        assert_eq!(repair.changes[0].new, vec![0xff, 0xff]);
+        assert_eq!((repair.changes[1].offset, &repair.changes[1].new[..]), (FAT1 + 3, &[0xff][..]));
+        assert!(!applied(&image, &repair.changes).open().fat().is_dirty());
+    }
+
+    #[test]
+    fn handles_image_truncated_within_the_fat() {
+        let mut image = Image::new();
+        image.set_chain(&[5, 6]);
+        image.set_chain(&[300, 301]);
+        image.bytes.truncate(FAT0 + 256 * 2);
+
+        let findings = image.open().check();
+        assert_eq!(findings.len(), 1);
+        assert_eq!((findings[0].problem, &findings[0].clusters[..]), (Problem::LostChain, &[5, 6][..]));
+
+        let repair = image.open().repair(&only(true));
+        let offsets: Vec<usize> = repair.changes.iter().map(|c| c.offset).collect();
+        assert_eq!(offsets, vec![FAT0 + 5 * 2, FAT0 + 6 * 2]);
+    }
+
+    #[test]
+    fn logs_one_line_per_sector() {
+        let mut image = Image::new();
+        image.set_chain(&[5, 6, 7, 8]);
+
+        let repair = image.open().repair(&only(false));
+        let mut log = Vec::new();
+        write_log(&mut log, BYTES_PER_SECTOR as u16, &repair.changes).unwrap();
+
+        assert_eq!(repair.changes.len(), 4);
+        assert_eq!(String::from_utf8(log).unwrap(),
+                   "sector 4\toffset 0x80a\t5 byte(s)\tfree lost cluster 5, free lost cluster 6, free lost cluster 7, free lost cluster 8\n");
+    }
+
+    #[test]
+    fn planning_writes_nothing_until_applied() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"BROKEN  TXT", 0x20, 2, 2048)]);
+        image.set_fat(2, 3);
+        image.set_fat(1, 0x7fff);
+        let path = image.save("repair-plan");
+
+        let mem = unsafe { memmap::MmapOptions::new().map(&File::open(&path).unwrap()).unwrap() };
+        let repair = Fat::new(mem).repair(&RepairOptions::default());
+        write_log(&mut io::sink(), BYTES_PER_SECTOR as u16, &repair.changes).unwrap();
+
+        assert!(!repair.changes.is_empty());
+        assert_eq!(std::fs::read(&path).unwrap(), image.bytes);
+
+        let mut file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        apply(&mut file, &repair.changes).unwrap();
+        let repaired = Image { bytes: std::fs::read(&path).unwrap() };
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(repaired.open().check().is_empty());
+    }
+}
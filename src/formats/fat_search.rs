@@ -0,0 +1,376 @@
+use super::fat::*;
+use super::fat_locate::ClusterIndex;
+use super::fat_reader::FileReader;
+use super::fat_recover;
+use super::fat_slack::Slack;
+use super::fat_table::cluster_runs;
+use super::fat_walk::WalkOptions;
+use regex::bytes::{Regex, RegexBuilder};
+use std::{
+    cmp,
+    collections::HashSet,
+    fmt,
+    io::{self, Read},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The encoding a pattern is searched in
+pub enum Encoding {
+    /// ASCII (and UTF-8)
+    Ascii,
+    /// UTF-16 little endian (e.g. long file names, Windows strings)
+    Utf16Le,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The part of the volume a hit was found in
+pub enum Area {
+    /// Content of a file that isn't deleted
+    Allocated,
+    /// Content of a deleted file (presumed clusters)
+    Deleted,
+    /// Slack of a file that isn't deleted
+    Slack,
+    /// Unallocated clusters that aren't part of a recoverable deleted file
+    Unallocated,
+}
+
+#[derive(Debug, Clone)]
+/// A keyword or regular expression in a specific encoding
+pub struct Pattern {
+    /// The keyword or expression as given by the user
+    pub name: String,
+    pub encoding: Encoding,
+    regex: Regex,
+}
+
+#[derive(Debug, Clone)]
+/// Selects the parts of the volume that are searched
+pub struct SearchOptions {
+    /// Search the content of files that aren't deleted
+    pub allocated: bool,
+    /// Search the content of recoverable deleted files
+    pub deleted: bool,
+    /// Search the slack of files that aren't deleted
+    pub slack: bool,
+    /// Search all other unallocated clusters
+    pub unallocated: bool,
+}
+
+#[derive(Debug, Clone)]
+/// A single match of a pattern
+pub struct Hit {
+    pub area: Area,
+    /// Index of the matching pattern
+    pub pattern: usize,
+    /// Byte offset of the first matching byte within the volume
+    pub offset: usize,
+    /// All clusters the match covers (more than one if it spans a cluster boundary)
+    pub clusters: Vec<u32>,
+    /// The file the match belongs to (for unallocated hits the deleted file that
+    /// presumably occupied the cluster, if any)
+    pub path: Option<String>,
+    /// Logical offset of the match within the file
+    pub file_offset: Option<u64>,
+    /// The matching bytes
+    pub bytes: Vec<u8>,
+}
+
+impl Pattern {
+    /// Returns the patterns that find a keyword in ASCII and in UTF-16LE
+    ///
+    /// # Arguments
+    ///
+    /// * `keyword` - The string to search for
+    /// * `ignore_case` - Compare ASCII letters case-insensitively
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use greasy::formats::fat_search::Pattern;
+    ///
+    /// let patterns = Pattern::keyword("Secret", true);
+    /// assert!(patterns[0].is_match(b"top SECRET"));
+    /// assert!(patterns[1].is_match(b"s\0e\0c\0r\0e\0t\0"));
+    /// ```
+    pub fn keyword(keyword: &str, ignore_case: bool) -> Vec<Pattern> {
+        let mut utf16 = String::new();
+        for unit in keyword.encode_utf16() {
+            match char::from_u32(unit as u32) {
+                Some(c) if c.is_ascii() => utf16.push_str(&format!("{}\\x00", regex::escape(&c.to_string()))),
+                _ => utf16.push_str(&format!("\\x{:02x}\\x{:02x}", unit & 0xff, unit >> 8)),
+            }
+        }
+
+        let build = |expr: &str, unicode: bool| {
+            RegexBuilder::new(expr)
+                .case_insensitive(ignore_case)
+                .unicode(unicode)
+                .build()
+                .expect("escaped keyword is a valid expression")
+        };
+
+        vec![
+            Pattern { name: keyword.to_string(), encoding: Encoding::Ascii, regex: build(&regex::escape(keyword), true) },
+            Pattern { name: keyword.to_string(), encoding: Encoding::Utf16Le, regex: build(&utf16, false) },
+        ]
+    }
+
+    /// Returns a pattern that matches a regular expression against the raw bytes
+    ///
+    /// The expression is matched as ASCII/UTF-8 only.
+    ///
+    /// # Arguments
+    ///
+    /// * `expr` - The regular expression (syntax of the `regex` crate)
+    /// * `ignore_case` - Match case-insensitively
+    pub fn regex(expr: &str, ignore_case: bool) -> io::Result<Pattern> {
+        let regex = RegexBuilder::new(expr)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        Ok(Pattern { name: expr.to_string(), encoding: Encoding::Ascii, regex })
+    }
+
+    /// Checks if the pattern matches anywhere within the data
+    pub fn is_match(&self, data: &[u8]) -> bool {
+        self.regex.is_match(data)
+    }
+}
+
+impl Hit {
+    /// Returns the matching bytes decoded using the encoding of the pattern
+    pub fn text(&self, pattern: &Pattern) -> String {
+        match pattern.encoding {
+            Encoding::Ascii => String::from_utf8_lossy(&self.bytes).to_string(),
+            Encoding::Utf16Le => {
+                let units: Vec<u16> = self.bytes.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+                String::from_utf16_lossy(&units)
+            },
+        }
+    }
+}
+
+impl Default for SearchOptions {
+    fn default() -> SearchOptions {
+        SearchOptions { allocated: true, deleted: true, slack: true, unallocated: true }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Encoding::Ascii => write!(f, "ascii"),
+            Encoding::Utf16Le => write!(f, "utf-16le"),
+        }
+    }
+}
+
+impl fmt::Display for Area {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Area::Allocated => write!(f, "allocated"),
+            Area::Deleted => write!(f, "deleted"),
+            Area::Slack => write!(f, "slack"),
+            Area::Unallocated => write!(f, "unallocated"),
+        }
+    }
+}
+
+/// Returns the cluster a byte of the cluster area belongs to
+fn cluster_of(fat: &Fat, offset: usize) -> u32 {
+    let first = fat.offset(&Sector(fat.start_cluster_area()));
+    ((offset - first) / fat.cluster_size()) as u32 + 2
+}
+
+/// Returns the (start, end) positions of all matches of all patterns
+fn matches(patterns: &[Pattern], data: &[u8]) -> Vec<(usize, usize, usize)> {
+    let mut found = Vec::new();
+
+    for (i, p) in patterns.iter().enumerate() {
+        for m in p.regex.find_iter(data) {
+            if !m.as_bytes().is_empty() {
+                found.push((i, m.start(), m.end()));
+            }
+        }
+    }
+
+    found
+}
+
+/// Search the logical content of a file
+///
+/// The content is searched as one stream, so matches that span two
+/// fragments of the file are found as well.
+fn search_file(fat: &Fat, reader: &mut FileReader, patterns: &[Pattern], area: Area, path: &str, hits: &mut Vec<Hit>) {
+    let mut content = Vec::new();
+    // files whose clusters lie outside of the image can't be searched
+    if reader.read_to_end(&mut content).is_err() {
+        return;
+    }
+
+    for (pattern, start, end) in matches(patterns, &content) {
+        let offsets: Vec<usize> = (start..end).filter_map(|pos| reader.volume_offset(pos as u64)).collect();
+        let mut clusters: Vec<u32> = offsets.iter().map(|o| cluster_of(fat, *o)).collect();
+        clusters.dedup();
+
+        hits.push(Hit {
+            area,
+            pattern,
+            offset: offsets[0],
+            clusters,
+            path: Some(path.to_string()),
+            file_offset: Some(start as u64),
+            bytes: content[start..end].to_vec(),
+        });
+    }
+}
+
+/// Search the volume for keywords or regular expressions
+///
+/// Hits are returned per area: first the files (content, slack), then the
+/// unallocated clusters. Matches within unallocated clusters that have
+/// already been reported for a deleted file are skipped.
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+/// * `patterns` - The patterns to search for
+/// * `options` - The parts of the volume to search
+pub fn search(fat: &dyn FAT, patterns: &[Pattern], options: &SearchOptions) -> Vec<Hit> {
+    let base = fat.fat();
+    let mut hits = Vec::new();
+
+    for item in fat.walk(WalkOptions::default()) {
+        let e = &item.entry;
+        if e.is_subdir_entry() || e.is_disk_volume_entry() {
+            continue;
+        }
+
+        if fat_recover::is_unlinked(base, e) {
+            if let (true, Some(r)) = (options.deleted, fat_recover::recover(base, e)) {
+                search_file(base, &mut FileReader::new(base, &r), patterns, Area::Deleted, &item.path, &mut hits);
+            }
+            continue;
+        }
+
+        if options.allocated {
+            search_file(base, &mut FileReader::new(base, e), patterns, Area::Allocated, &item.path, &mut hits);
+        }
+
+        if let (true, Some(slack)) = (options.slack, Slack::new(base, &item.path, e)) {
+            for (pattern, start, end) in matches(patterns, &slack.data) {
+                hits.push(Hit {
+                    area: Area::Slack,
+                    pattern,
+                    offset: slack.offset + start,
+                    clusters: vec![slack.cluster],
+                    path: Some(item.path.clone()),
+                    file_offset: Some(e.size() as u64 + start as u64),
+                    bytes: slack.data[start..end].to_vec(),
+                });
+            }
+        }
+    }
+
+    if options.unallocated {
+        let reported: HashSet<(usize, usize)> = hits.iter()
+            .filter(|h| h.area == Area::Deleted)
+            .map(|h| (h.pattern, h.offset))
+            .collect();
+        let index = ClusterIndex::new(fat);
+        let free: Vec<u32> = (2..=base.total_clusters()).filter(|c| fat_recover::is_free(base, *c)).collect();
+
+        // runs of free clusters are searched as one block to find matches spanning clusters
+        for (first, last) in cluster_runs(&free) {
+            let start = base.clusters_to_offsets(&[Cluster(first)])[0];
+            let end = cmp::min(base.clusters_to_offsets(&[Cluster(last)])[0] + base.cluster_size(), base.mem().len());
+            if start >= end {
+                continue;
+            }
+            let data = &base.mem()[start..end];
+
+            for (pattern, s, e) in matches(patterns, data) {
+                if reported.contains(&(pattern, start + s)) {
+                    continue;
+                }
+
+                let first_cluster = cluster_of(base, start + s);
+                let owner = index.owners(first_cluster).iter().find(|o| o.deleted).map(|o| o.path.clone());
+
+                hits.push(Hit {
+                    area: Area::Unallocated,
+                    pattern,
+                    offset: start + s,
+                    clusters: (first_cluster..=cluster_of(base, start + e - 1)).collect(),
+                    path: owner,
+                    file_offset: None,
+                    bytes: data[s..e].to_vec(),
+                });
+            }
+        }
+    }
+
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fat_test::*;
+
+    fn allocated_only() -> SearchOptions {
+        SearchOptions { allocated: true, deleted: false, slack: false, unallocated: false }
+    }
+
+    #[test]
+    fn finds_keyword_split_across_fragments() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"SPLIT   TXT", 0x20, 3, 2 * CLUSTER_SIZE as u32)]);
+        image.set_chain(&[3, 5]);
+        image.write(Image::cluster_offset(3) + CLUSTER_SIZE - 3, b"SEC");
+        image.write(Image::cluster_offset(5), b"RET");
+
+        let hits = search(&*image.open(), &Pattern::keyword("secret", true), &allocated_only());
+
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].area, Area::Allocated);
+        assert_eq!(hits[0].clusters, vec![3, 5]);
+        assert_eq!(hits[0].offset, Image::cluster_offset(3) + CLUSTER_SIZE - 3);
+        assert_eq!(hits[0].file_offset, Some(CLUSTER_SIZE as u64 - 3));
+        assert_eq!(hits[0].bytes, b"SECRET");
+    }
+
+    #[test]
+    fn finds_utf16le_keyword() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"WIDE    TXT", 0x20, 3, 20)]);
+        image.set_chain(&[3]);
+        image.write(Image::cluster_offset(3) + 4, b"s\0e\0c\0r\0e\0t\0");
+
+        let patterns = Pattern::keyword("Secret", true);
+        let hits = search(&*image.open(), &patterns, &allocated_only());
+
+        assert_eq!(hits.len(), 1);
+        assert_eq!(patterns[hits[0].pattern].encoding, Encoding::Utf16Le);
+        assert_eq!(hits[0].file_offset, Some(4));
+        assert_eq!(hits[0].text(&patterns[hits[0].pattern]), "secret");
+    }
+
+    #[test]
+    fn skips_unallocated_hits_of_deleted_files() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"\xe5ELETED TXT", 0x20, 7, 100)]);
+        image.write(Image::cluster_offset(7) + 10, b"SECRET");
+        image.write(Image::cluster_offset(20), b"SECRET");
+
+        let hits = search(&*image.open(), &Pattern::keyword("SECRET", false), &SearchOptions::default());
+        let found: Vec<(Area, usize, Option<&str>)> = hits.iter().map(|h| (h.area, h.offset, h.path.as_deref())).collect();
+
+        assert_eq!(found, vec![
+            (Area::Deleted, Image::cluster_offset(7) + 10, Some("/_ELETED.TXT")),
+            (Area::Unallocated, Image::cluster_offset(20), None),
+        ]);
+    }
+}
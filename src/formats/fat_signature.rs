@@ -0,0 +1,81 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Magic bytes that identify the format of a file
+pub struct Signature {
+    /// Name of the format (e.g. `JPEG`)
+    pub name: &'static str,
+    /// Usual file extensions of the format (upper case)
+    pub extensions: &'static [&'static str],
+    /// The magic bytes
+    pub magic: &'static [u8],
+    /// Offset of the magic bytes from the beginning of the file
+    pub offset: usize,
+}
+
+/// Signatures of common file formats
+pub const SIGNATURES: &[Signature] = &[
+    Signature { name: "JPEG", extensions: &["JPG", "JPEG", "JPE"], magic: b"\xff\xd8\xff", offset: 0 },
+    Signature { name: "PNG", extensions: &["PNG"], magic: b"\x89PNG\r\n\x1a\n", offset: 0 },
+    Signature { name: "GIF", extensions: &["GIF"], magic: b"GIF8", offset: 0 },
+    Signature { name: "BMP", extensions: &["BMP"], magic: b"BM", offset: 0 },
+    Signature { name: "TIFF", extensions: &["TIF", "TIFF"], magic: b"II*\0", offset: 0 },
+    Signature { name: "TIFF", extensions: &["TIF", "TIFF"], magic: b"MM\0*", offset: 0 },
+    Signature { name: "PDF", extensions: &["PDF"], magic: b"%PDF-", offset: 0 },
+    Signature { name: "ZIP", extensions: &["ZIP", "DOCX", "XLSX", "PPTX", "ODT", "JAR", "APK"], magic: b"PK\x03\x04", offset: 0 },
+    Signature { name: "OLE2", extensions: &["DOC", "XLS", "PPT", "MSG"], magic: b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1", offset: 0 },
+    Signature { name: "GZIP", extensions: &["GZ", "TGZ"], magic: b"\x1f\x8b\x08", offset: 0 },
+    Signature { name: "BZIP2", extensions: &["BZ2"], magic: b"BZh", offset: 0 },
+    Signature { name: "7-Zip", extensions: &["7Z"], magic: b"7z\xbc\xaf\x27\x1c", offset: 0 },
+    Signature { name: "RAR", extensions: &["RAR"], magic: b"Rar!\x1a\x07", offset: 0 },
+    Signature { name: "TAR", extensions: &["TAR"], magic: b"ustar", offset: 257 },
+    Signature { name: "ELF", extensions: &["", "SO", "O"], magic: b"\x7fELF", offset: 0 },
+    Signature { name: "PE", extensions: &["EXE", "DLL", "SYS", "SCR"], magic: b"MZ", offset: 0 },
+    Signature { name: "MP3", extensions: &["MP3"], magic: b"ID3", offset: 0 },
+    Signature { name: "RIFF", extensions: &["WAV", "AVI", "WEBP"], magic: b"RIFF", offset: 0 },
+    Signature { name: "MP4", extensions: &["MP4", "M4A", "MOV"], magic: b"ftyp", offset: 4 },
+    Signature { name: "SQLite", extensions: &["DB", "SQLITE"], magic: b"SQLite format 3\0", offset: 0 },
+];
+
+impl Signature {
+    /// Checks if the given data starts with this signature
+    pub fn matches(&self, data: &[u8]) -> bool {
+        data.get(self.offset..self.offset + self.magic.len()) == Some(self.magic)
+    }
+}
+
+/// Returns the signature the given data starts with
+///
+/// # Examples
+///
+/// ```
+/// use greasy::formats::fat_signature::identify;
+///
+/// assert_eq!(Some("JPEG"), identify(b"\xff\xd8\xff\xe0 JFIF").map(|s| s.name));
+/// assert_eq!(None, identify(b"hello world"));
+/// ```
+pub fn identify(data: &[u8]) -> Option<&'static Signature> {
+    SIGNATURES.iter().find(|s| s.matches(data))
+}
+
+/// Returns every position within the data at which a signature starts
+///
+/// Signatures with an offset are reported at the position the file would
+/// start at. Two byte signatures (`BM`, `MZ`) are ignored because they
+/// produce too many false hits in arbitrary data.
+///
+/// # Arguments
+///
+/// * `data` - The data to search
+pub fn find_signatures(data: &[u8]) -> Vec<(usize, &'static Signature)> {
+    let mut hits = Vec::new();
+
+    for s in SIGNATURES.iter().filter(|s| s.magic.len() > 2) {
+        for (i, window) in data.windows(s.magic.len()).enumerate() {
+            if window == s.magic && i >= s.offset {
+                hits.push((i - s.offset, s));
+            }
+        }
+    }
+
+    hits.sort_by_key(|(i, _)| *i);
+    hits
+}
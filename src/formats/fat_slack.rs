@@ -0,0 +1,130 @@
+use super::fat::*;
+use super::fat_entry::*;
+use super::fat_recover;
+use super::fat_signature::{self, Signature};
+use super::fat_walk::WalkOptions;
+
+#[derive(Debug, Clone)]
+/// The slack space of a single file
+///
+/// RAM slack lies between the end of the file and the end of its last
+/// sector, file slack between the end of that sector and the end of the
+/// last cluster.
+pub struct Slack {
+    /// Absolute path of the file
+    pub path: String,
+    /// Is the file deleted (or within a deleted directory)? [yes/no] (its slack is taken from its presumed clusters)
+    pub deleted: bool,
+    /// The last cluster of the file
+    pub cluster: u32,
+    /// Byte offset of the first slack byte within the volume
+    pub offset: usize,
+    /// Number of RAM slack bytes (at the beginning of `data`)
+    pub ram_len: usize,
+    /// RAM slack followed by file slack
+    pub data: Vec<u8>,
+}
+
+impl Slack {
+    /// Returns the slack of a file
+    ///
+    /// Returns None for directories, empty files, files that end at a cluster
+    /// boundary and files whose chain is shorter than their size. The slack
+    /// of unlinked files (see `fat_recover::is_unlinked()`) is taken from
+    /// their presumed clusters.
+    ///
+    /// # Arguments
+    ///
+    /// * `fat` - The file system the entry belongs to
+    /// * `path` - Absolute path of the file
+    /// * `e` - The directory entry
+    pub fn new(fat: &Fat, path: &str, e: &Entry) -> Option<Slack> {
+        let size = e.size() as usize;
+        let cluster_size = fat.cluster_size();
+
+        if e.is_subdir_entry() || e.is_disk_volume_entry() || size == 0 || size % cluster_size == 0 {
+            return None;
+        }
+
+        let deleted = fat_recover::is_unlinked(fat, e);
+        let clusters: Vec<u32> = if deleted {
+            fat_recover::presumed_clusters(fat, e).iter().map(|c| c.0).collect()
+        } else {
+            e.clusters().iter().flatten().map(|c| c.0).collect()
+        };
+        let cluster = *clusters.get((size - 1) / cluster_size)?;
+
+        let start = fat.clusters_to_offsets(&[Cluster(cluster)])[0];
+        let offset = start + size % cluster_size;
+        let end = start + cluster_size;
+        if end > fat.mem().len() {
+            return None;
+        }
+
+        let bps = fat.bytes_per_sector() as usize;
+        let ram_len = (bps - (size % bps)) % bps;
+
+        Some(Slack {
+            path: path.to_string(),
+            deleted,
+            cluster,
+            offset,
+            ram_len,
+            data: fat.mem()[offset..end].to_vec(),
+        })
+    }
+
+    /// Returns the RAM slack (up to the end of the last sector of the file)
+    pub fn ram(&self) -> &[u8] {
+        &self.data[..self.ram_len]
+    }
+
+    /// Returns the file slack (the unused sectors of the last cluster)
+    pub fn file(&self) -> &[u8] {
+        &self.data[self.ram_len..]
+    }
+
+    /// Returns the number of non-zero bytes of the whole slack
+    pub fn non_zero(&self) -> usize {
+        self.data.iter().filter(|b| **b != 0).count()
+    }
+
+    /// Returns every position within the slack at which the pattern occurs
+    ///
+    /// ASCII letters are compared case-insensitively.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The bytes to search for
+    pub fn find(&self, pattern: &[u8]) -> Vec<usize> {
+        if pattern.is_empty() {
+            return Vec::new();
+        }
+
+        self.data.windows(pattern.len())
+            .enumerate()
+            .filter(|(_, w)| w.eq_ignore_ascii_case(pattern))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Returns every position within the slack at which a file signature starts
+    pub fn signatures(&self) -> Vec<(usize, &'static Signature)> {
+        fat_signature::find_signatures(&self.data)
+    }
+}
+
+/// Returns the slack of every file of the volume
+///
+/// # Arguments
+///
+/// * `fat` - The file system
+/// * `include_deleted` - Include deleted files whose clusters haven't been allocated again
+pub fn slack_files(fat: &dyn FAT, include_deleted: bool) -> Vec<Slack> {
+    let options = WalkOptions { include_deleted, ..WalkOptions::default() };
+
+    fat.walk(options)
+        .filter(|item| !fat_recover::is_unlinked(fat.fat(), &item.entry) || fat_recover::recover(fat.fat(), &item.entry).is_some())
+        .filter_map(|item| Slack::new(fat.fat(), &item.path, &item.entry))
+        .collect()
+}
@@ -0,0 +1,116 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// TableEntry represents the decoded value of a single FAT table entry
+///
+/// # FAT table Entry types
+/// ## Fat16
+/// 1. unused/ free cluster: 0x0000
+/// 2. bad cluster: 0xfff7
+/// 3. address of next cluster: n
+/// 4. last cluster in a file (EOF): 0xfff8 - 0xffff
+///
+/// ## Fat32
+/// 1. unused/ free cluster: 0x0000
+/// 2. bad cluster: 0x0ffffff7
+/// 3. address of next cluster: n
+/// 4. last cluster in a file (EOF): 0x0ffffff8 - 0x0fffffff
+///
+/// The upper 4 bits of a FAT32 entry are reserved and ignored.
+pub enum TableEntry {
+    /// Unused cluster
+    Free,
+    /// Reserved value (1 or the range right below the bad cluster marker)
+    Reserved(u32),
+    /// Address of the next cluster of the chain
+    Next(u32),
+    /// Bad cluster marker
+    Bad,
+    /// Last cluster of the chain
+    EndOfChain,
+}
+
+#[derive(Debug)]
+/// A run of consecutive clusters whose FAT entries differ between the FAT copies
+pub struct Divergence {
+    /// First cluster of the run
+    pub first: u32,
+    /// Last cluster of the run
+    pub last: u32,
+    /// Raw FAT entries of every cluster of the run, one value per FAT copy
+    pub entries: Vec<Vec<u32>>,
+    /// Paths of all files whose cluster chain (in any FAT copy) touches the run
+    pub files: Vec<String>,
+}
+
+impl TableEntry {
+    /// Decode a raw FAT table entry
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The raw value read from the FAT table
+    /// * `entry_size` - Number of bits per FAT table entry (16 or 32)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use greasy::formats::fat_table::TableEntry;
+    ///
+    /// assert_eq!(TableEntry::Free, TableEntry::new(0, 16));
+    /// assert_eq!(TableEntry::Next(5), TableEntry::new(5, 16));
+    /// assert_eq!(TableEntry::Bad, TableEntry::new(0xfff7, 16));
+    /// assert_eq!(TableEntry::EndOfChain, TableEntry::new(0xffff, 16));
+    /// assert_eq!(TableEntry::EndOfChain, TableEntry::new(0xfffffff8, 32));
+    /// assert_eq!(TableEntry::Next(0xfff8), TableEntry::new(0xfff8, 32));
+    /// ```
+    pub fn new(value: u32, entry_size: u8) -> TableEntry {
+        let (value, bad) = match entry_size {
+            16 => (value & 0xffff, 0xfff7),
+            _ => (value & 0x0fff_ffff, 0x0fff_fff7),
+        };
+
+        match value {
+            0 => TableEntry::Free,
+            1 => TableEntry::Reserved(value),
+            v if v == bad => TableEntry::Bad,
+            v if v > bad => TableEntry::EndOfChain,
+            v if v >= bad - 7 => TableEntry::Reserved(v),
+            v => TableEntry::Next(v),
+        }
+    }
+}
+
+/// Group a cluster chain into runs of consecutive clusters (first, last)
+///
+/// # Examples
+///
+/// ```
+/// use greasy::formats::fat_table::cluster_runs;
+///
+/// assert_eq!(vec![(2, 4), (7, 7), (9, 10)], cluster_runs(&[2, 3, 4, 7, 9, 10]));
+/// assert!(cluster_runs(&[]).is_empty());
+/// ```
+pub fn cluster_runs(clusters: &[u32]) -> Vec<(u32, u32)> {
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+
+    for c in clusters {
+        match runs.last_mut() {
+            Some(r) if r.1 + 1 == *c => r.1 = *c,
+            _ => runs.push((*c, *c)),
+        }
+    }
+
+    runs
+}
+
+impl fmt::Display for TableEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableEntry::Free => write!(f, "free"),
+            TableEntry::Reserved(v) => write!(f, "reserved (0x{:x})", v),
+            TableEntry::Next(n) => write!(f, "next {}", n),
+            TableEntry::Bad => write!(f, "bad"),
+            TableEntry::EndOfChain => write!(f, "EOF"),
+        }
+    }
+}
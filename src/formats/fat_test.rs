@@ -0,0 +1,169 @@
+//! Small in-memory FAT16 volumes for unit tests
+//!
+//! Layout (512 bytes per sector, 2 sectors per cluster): reserved sectors
+//! 0 - 3, FAT 0 in sectors 4 - 5, FAT 1 in sectors 6 - 7, 64 root directory
+//! entries in sectors 8 - 11 and clusters 2 - 507 from sector 12 on.
+
+use super::fat::*;
+use super::fat_entry::Entry;
+use byteorder::{ByteOrder, LittleEndian};
+use memmap::{Mmap, MmapMut};
+use std::{fs, path::PathBuf};
+
+pub const BYTES_PER_SECTOR: usize = 512;
+pub const CLUSTER_SIZE: usize = 1024;
+const TOTAL_SECTORS: usize = 1024;
+const FAT_SECTORS: usize = 2;
+const ROOT_SECTOR: usize = 8;
+const CLUSTER_SECTOR: usize = 12;
+
+/// Raw bytes of a FAT16 volume
+pub struct Image {
+    pub bytes: Vec<u8>,
+}
+
+impl Image {
+    /// Returns an empty, cleanly unmounted volume
+    pub fn new() -> Image {
+        let mut bytes = vec![0; TOTAL_SECTORS * BYTES_PER_SECTOR];
+
+        bytes[0..3].copy_from_slice(&[0xeb, 0x3c, 0x90]);
+        bytes[3..11].copy_from_slice(b"mkfs.fat");
+        LittleEndian::write_u16(&mut bytes[11..13], BYTES_PER_SECTOR as u16);
+        bytes[13] = (CLUSTER_SIZE / BYTES_PER_SECTOR) as u8;
+        LittleEndian::write_u16(&mut bytes[14..16], 4);
+        bytes[16] = 2;
+        LittleEndian::write_u16(&mut bytes[17..19], 64);
+        LittleEndian::write_u16(&mut bytes[19..21], TOTAL_SECTORS as u16);
+        bytes[21] = 0xf8;
+        LittleEndian::write_u16(&mut bytes[22..24], FAT_SECTORS as u16);
+        bytes[36] = 0x80;
+        bytes[38] = 0x29;
+        bytes[43..54].copy_from_slice(b"TEST       ");
+        bytes[54..62].copy_from_slice(b"FAT16   ");
+        bytes[510..512].copy_from_slice(&[0x55, 0xaa]);
+
+        let mut image = Image { bytes };
+        image.set_fat(0, 0xfff8);
+        image.set_fat(1, 0xffff);
+        image
+    }
+
+    /// Set the FAT entry of a cluster in both FAT tables
+    pub fn set_fat(&mut self, cluster: u32, value: u16) {
+        self.set_fat_in(0, cluster, value);
+        self.set_fat_in(1, cluster, value);
+    }
+
+    /// Set the FAT entry of a cluster in one FAT table
+    pub fn set_fat_in(&mut self, fat: usize, cluster: u32, value: u16) {
+        let offset = (4 + fat * FAT_SECTORS) * BYTES_PER_SECTOR + cluster as usize * 2;
+        LittleEndian::write_u16(&mut self.bytes[offset..offset + 2], value);
+    }
+
+    /// Link the given clusters to a chain that ends with an end of chain marker
+    pub fn set_chain(&mut self, clusters: &[u32]) {
+        for (i, c) in clusters.iter().enumerate() {
+            self.set_fat(*c, clusters.get(i + 1).map_or(0xffff, |n| *n as u16));
+        }
+    }
+
+    /// Returns the byte offset of a cluster
+    pub fn cluster_offset(cluster: u32) -> usize {
+        (CLUSTER_SECTOR + (cluster as usize - 2) * CLUSTER_SIZE / BYTES_PER_SECTOR) * BYTES_PER_SECTOR
+    }
+
+    /// Returns the byte offset of the n'th slot of the root directory
+    pub fn root_offset(slot: usize) -> usize {
+        ROOT_SECTOR * BYTES_PER_SECTOR + slot * 32
+    }
+
+    /// Write directory entries to the root directory, starting at the given slot
+    pub fn set_root(&mut self, slot: usize, entries: &[[u8; 32]]) {
+        self.write(Image::root_offset(slot), &entries.concat());
+    }
+
+    /// Write directory entries to a cluster, starting at the given slot
+    pub fn set_dir(&mut self, cluster: u32, slot: usize, entries: &[[u8; 32]]) {
+        self.write(Image::cluster_offset(cluster) + slot * 32, &entries.concat());
+    }
+
+    /// Write bytes at the given offset
+    pub fn write(&mut self, offset: usize, data: &[u8]) {
+        self.bytes[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    /// Returns a read-only memory map of the volume
+    pub fn mmap(&self) -> Mmap {
+        let mut mem = MmapMut::map_anon(self.bytes.len()).unwrap();
+        mem.copy_from_slice(&self.bytes);
+        mem.make_read_only().unwrap()
+    }
+
+    /// Open the volume
+    pub fn open(&self) -> Box<dyn FAT> {
+        Fat::new(self.mmap())
+    }
+
+    /// Write the volume to a temporary file and return its path
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Unique name of the file
+    pub fn save(&self, name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("greasy-{}-{}.dd", std::process::id(), name));
+        fs::write(&path, &self.bytes).unwrap();
+        path
+    }
+}
+
+/// Build a 32 byte short directory entry
+pub fn short_entry(name: &[u8; 11], attributes: u8, start: u32, size: u32) -> [u8; 32] {
+    let mut e = [0; 32];
+    e[..11].copy_from_slice(name);
+    e[11] = attributes;
+    LittleEndian::write_u16(&mut e[20..22], (start >> 16) as u16);
+    LittleEndian::write_u16(&mut e[26..28], start as u16);
+    LittleEndian::write_u32(&mut e[28..32], size);
+    e
+}
+
+/// Build a short directory entry and the LFN entries in front of it (in on-disk order)
+///
+/// The first byte of every entry is replaced by 0xe5 if `deleted` is set.
+pub fn named_entry(long: &str, name: &[u8; 11], attributes: u8, start: u32, size: u32, deleted: bool) -> Vec<[u8; 32]> {
+    let checksum = Entry::checksum_bytes(name);
+    let mut chars: Vec<u16> = long.encode_utf16().collect();
+    if chars.len() % 13 != 0 {
+        chars.push(0);
+    }
+    while chars.len() % 13 != 0 {
+        chars.push(0xffff);
+    }
+
+    let parts = chars.len() / 13;
+    let mut entries = Vec::new();
+    for (i, part) in chars.chunks(13).enumerate().rev() {
+        let mut e = [0; 32];
+        e[0] = (i + 1) as u8 | if i + 1 == parts { 0x40 } else { 0 };
+        for (j, c) in part.iter().enumerate() {
+            let at = match j {
+                0..=4 => 1 + j * 2,
+                5..=10 => 14 + (j - 5) * 2,
+                _ => 28 + (j - 11) * 2,
+            };
+            LittleEndian::write_u16(&mut e[at..at + 2], *c);
+        }
+        e[11] = 0x0f;
+        e[13] = checksum;
+        entries.push(e);
+    }
+    entries.push(short_entry(name, attributes, start, size));
+
+    if deleted {
+        for e in &mut entries {
+            e[0] = 0xe5;
+        }
+    }
+    entries
+}
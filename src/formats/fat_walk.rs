@@ -0,0 +1,285 @@
+use super::fat::*;
+use super::fat_entry::*;
+use super::fat_recover;
+use std::collections::{HashSet, VecDeque};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The order in which a walk visits the entries of the volume
+pub enum Order {
+    /// Descend into a directory right after it has been visited
+    DepthFirst,
+    /// Visit all entries of a level before descending to the next one
+    BreadthFirst,
+}
+
+#[derive(Debug, Clone)]
+/// Selects which entries a walk visits
+pub struct WalkOptions {
+    /// Traversal order
+    pub order: Order,
+    /// Visit deleted entries (and descend into deleted directories)
+    pub include_deleted: bool,
+    /// Don't descend below this depth (entries of the root directory have depth 1)
+    pub max_depth: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+/// A single entry visited during a walk
+pub struct WalkEntry {
+    /// Absolute path of the entry (e.g. `/Pictures/my dog.jpg`)
+    pub path: String,
+    /// Number of directories from the root directory (1 for entries of the root directory)
+    pub depth: usize,
+    /// The directory entry
+    pub entry: Entry,
+}
+
+#[derive(Debug, Default)]
+/// Remembers the deleted directories of a walk
+///
+/// Entries of a deleted directory usually aren't marked as deleted themselves,
+/// so a deleted ancestor is the only hint that their data isn't linked anymore.
+pub struct DeletedDirs {
+    /// Paths of deleted directories and of all directories below them
+    paths: HashSet<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Tells a visitor based walk how to continue
+pub enum Visit {
+    /// Continue with the next entry
+    Continue,
+    /// Don't descend into the directory that was just visited
+    SkipSubtree,
+    /// End the walk
+    Stop,
+}
+
+/// Iterator over all entries of a volume
+///
+/// Sub directories are read lazily: a directory is only read once the walk
+/// moves past it, so `skip_subtree()` can be called right after a directory
+/// was returned to prevent the walk from descending into it. Every directory
+/// is entered at most once, which protects against sub directories whose
+/// start cluster points at an ancestor (or the root directory). Deleted
+/// directories are tracked separately, so they can't hide a live directory
+/// that reuses their start cluster.
+///
+/// # Examples
+///
+/// ```no_run
+/// use greasy::formats::{fat, fat_walk::WalkOptions};
+/// use memmap::MmapOptions;
+/// use std::fs::File;
+///
+/// let file = File::open("fat-16.dd").unwrap();
+/// let mem = unsafe { MmapOptions::new().map(&file).unwrap() };
+/// let fat = fat::Fat::new(mem);
+///
+/// for item in fat.walk(WalkOptions::default()) {
+///     println!("{} {}", item.depth, item.path);
+/// }
+/// ```
+pub struct Walk<'a> {
+    fat: &'a dyn FAT,
+    options: WalkOptions,
+    /// Entries that haven't been visited yet
+    queue: VecDeque<WalkEntry>,
+    /// Start clusters of all linked directories entered so far
+    visited: HashSet<u32>,
+    /// Start clusters of all unlinked (deleted) directories entered so far
+    visited_unlinked: HashSet<u32>,
+    /// The directory returned by the last call to next() (not read yet)
+    pending: Option<WalkEntry>,
+}
+
+impl Default for WalkOptions {
+    fn default() -> WalkOptions {
+        WalkOptions {
+            order: Order::DepthFirst,
+            include_deleted: true,
+            max_depth: None,
+        }
+    }
+}
+
+impl DeletedDirs {
+    /// Checks if an entry or any of its ancestors is deleted
+    ///
+    /// The entries must be passed in walk order (directories before their content).
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The entry visited by the walk
+    pub fn is_deleted(&mut self, item: &WalkEntry) -> bool {
+        let parent = item.path.rsplit_once('/').map_or("", |(parent, _)| parent);
+        let deleted = item.entry.is_deleted() || self.paths.contains(parent);
+
+        if deleted && item.entry.is_subdir_entry() && !item.entry.is_disk_volume_entry() {
+            self.paths.insert(item.path.clone());
+        }
+
+        deleted
+    }
+}
+
+impl<'a> Walk<'a> {
+    /// Returns a walk over all entries of the volume
+    ///
+    /// # Arguments
+    ///
+    /// * `fat` - The file system to walk
+    /// * `options` - Traversal order and filters
+    pub fn new(fat: &'a dyn FAT, options: WalkOptions) -> Walk<'a> {
+        // the root directory (cluster 0 on FAT16) must never be entered again
+        let mut visited: HashSet<u32> = fat.fat().root_clusters().into_iter().collect();
+        visited.insert(0);
+
+        let mut walk = Walk { fat, options, queue: VecDeque::new(), visited, visited_unlinked: HashSet::new(), pending: None };
+        let root: Vec<Entry> = fat.root_dir().collect();
+        walk.enqueue("", 1, root);
+        walk
+    }
+
+    /// Don't descend into the directory returned by the last call to next()
+    pub fn skip_subtree(&mut self) {
+        self.pending = None;
+    }
+
+    /// Walk the volume and call `visitor` for every entry
+    ///
+    /// # Arguments
+    ///
+    /// * `visitor` - Decides for every entry if the walk continues, skips the subtree or stops
+    pub fn visit<V: FnMut(&WalkEntry) -> Visit>(mut self, mut visitor: V) {
+        while let Some(item) = self.next() {
+            match visitor(&item) {
+                Visit::Continue => (),
+                Visit::SkipSubtree => self.skip_subtree(),
+                Visit::Stop => break,
+            }
+        }
+    }
+
+    /// Add the entries of a directory to the queue
+    fn enqueue(&mut self, path: &str, depth: usize, entries: Vec<Entry>) {
+        let items: Vec<WalkEntry> = entries.into_iter()
+            .filter(|e| self.options.include_deleted || !e.is_deleted())
+            .map(|e| WalkEntry { path: format!("{}/{}", path, e.name()), depth, entry: e })
+            .collect();
+
+        match self.options.order {
+            Order::DepthFirst => {
+                for item in items.into_iter().rev() {
+                    self.queue.push_front(item);
+                }
+            },
+            Order::BreadthFirst => self.queue.extend(items),
+        }
+    }
+
+    /// Read the pending directory and add its entries to the queue
+    fn expand(&mut self) {
+        let dir = match self.pending.take() {
+            Some(dir) => dir,
+            None => return,
+        };
+
+        if self.options.max_depth.is_some_and(|max| dir.depth >= max) {
+            return;
+        }
+
+        let start = dir.entry.start().0;
+        let first_visit = if fat_recover::is_unlinked(self.fat.fat(), &dir.entry) {
+            !self.visited.contains(&start) && self.visited_unlinked.insert(start)
+        } else {
+            self.visited.insert(start)
+        };
+        if !first_visit {
+            return;
+        }
+
+        if let Ok(entries) = self.fat.read_dir(&dir.entry) {
+            self.enqueue(&dir.path, dir.depth + 1, entries.collect());
+        }
+    }
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = WalkEntry;
+
+    fn next(&mut self) -> Option<WalkEntry> {
+        self.expand();
+
+        let item = self.queue.pop_front()?;
+        if item.entry.is_subdir_entry() && !item.entry.is_disk_volume_entry() {
+            self.pending = Some(item.clone());
+        }
+
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fat_test::*;
+
+    fn paths(fat: &dyn FAT) -> Vec<String> {
+        fat.walk(WalkOptions::default()).map(|w| w.path).collect()
+    }
+
+    #[test]
+    fn deleted_directory_doesnt_hide_live_directory() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"X          ", 0x10, 3, 0), short_entry(b"B          ", 0x10, 5, 0)]);
+        image.set_chain(&[3]);
+        image.set_chain(&[5]);
+        // the deleted directory /X/_A started at the cluster now used by /B
+        image.set_dir(3, 0, &[short_entry(b"\xe5A         ", 0x10, 5, 0)]);
+        image.set_dir(5, 0, &[short_entry(b"FILE    TXT", 0x20, 0, 0)]);
+
+        assert_eq!(paths(&*image.open()), vec!["/X", "/X/_A", "/B", "/B/FILE.TXT"]);
+    }
+
+    #[test]
+    fn entries_of_deleted_directories_are_deleted() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"\xe5IR        ", 0x10, 3, 0), short_entry(b"LIVE    TXT", 0x20, 0, 0)]);
+        image.set_dir(3, 0, &[
+            short_entry(b".          ", 0x10, 3, 0),
+            short_entry(b"..         ", 0x10, 0, 0),
+            short_entry(b"SUB        ", 0x10, 4, 0),
+        ]);
+        image.set_dir(4, 0, &[
+            short_entry(b".          ", 0x10, 4, 0),
+            short_entry(b"..         ", 0x10, 3, 0),
+            short_entry(b"FILE    TXT", 0x20, 0, 0),
+        ]);
+
+        let fat = image.open();
+        let mut deleted = DeletedDirs::default();
+        let items: Vec<(String, bool)> = fat.walk(WalkOptions::default())
+            .filter(|w| !w.entry.is_this_entry() && !w.entry.is_prev_entry())
+            .map(|w| (w.path.clone(), deleted.is_deleted(&w)))
+            .collect();
+
+        assert_eq!(items, vec![
+            ("/_IR".to_string(), true),
+            ("/_IR/SUB".to_string(), true),
+            ("/_IR/SUB/FILE.TXT".to_string(), true),
+            ("/LIVE.TXT".to_string(), false),
+        ]);
+    }
+
+    #[test]
+    fn directories_are_entered_once() {
+        let mut image = Image::new();
+        image.set_root(0, &[short_entry(b"A          ", 0x10, 3, 0)]);
+        image.set_chain(&[3]);
+        // a sub directory that points back to its parent and one to the root directory
+        image.set_dir(3, 0, &[short_entry(b"LOOP       ", 0x10, 3, 0), short_entry(b"ROOT       ", 0x10, 0, 0)]);
+
+        assert_eq!(paths(&*image.open()), vec!["/A", "/A/LOOP", "/A/ROOT"]);
+    }
+}
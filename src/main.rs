@@ -1,15 +1,636 @@
 use memmap::MmapOptions;
 use std::{
-    fs::File,
+    convert::TryFrom,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, Error, ErrorKind},
+    path::Path,
 };
-use greasy::formats::fat;
-use clap::{Arg, App, SubCommand};
+use greasy::formats::{
+    fat::{self, FAT},
+    fat_entry::Entry,
+    fat_reader::FileReader,
+    fat_check::Severity,
+    fat_repair::{self, RepairOptions},
+    fat_walk::{WalkEntry, WalkOptions},
+    fat_json,
+    fat_listing,
+    fat_dfxml,
+    fat_hash::{self, HashDatabase, Status},
+    fat_istat,
+    fat_dump,
+    fat_recover,
+    fat_slack,
+    fat_search::{self, Pattern, SearchOptions},
+    fat_check::cluster_ranges,
+    fat_hidden,
+    fat_dirslack,
+    fat_orphan,
+    fat_locate::ClusterIndex,
+    fat_assess,
+};
+use clap::{Arg, App, AppSettings, ArgMatches, SubCommand};
+
+/// Map the volume given as INPUT into memory and parse it
+fn open(matches: &ArgMatches) -> std::io::Result<Box<dyn FAT>> {
+    let file = File::open(matches.value_of("INPUT").unwrap())?;
+    let mem = unsafe { MmapOptions::new().map(&file)? };
+
+    let mut fat = if matches.is_present("backup") {
+        fat::Fat::from_backup(mem)
+    } else {
+        fat::Fat::new(mem)
+    };
+
+    if let Some(n) = matches.value_of("fat") {
+        let n = n.parse::<u8>().map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        fat.select_fat(n)?;
+    }
+
+    if let Some(placeholder) = matches.value_of("placeholder") {
+        let mut chars = placeholder.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => fat.set_placeholder(c),
+            _ => return Err(Error::new(ErrorKind::InvalidInput, format!("{}: placeholder must be a single character", placeholder))),
+        }
+    }
+
+    Ok(fat)
+}
+
+/// Parse a decimal or hexadecimal (0x prefix) number
+fn parse_number(s: &str) -> std::io::Result<usize> {
+    let n = match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse::<usize>(),
+    };
+
+    n.map_err(|e| Error::new(ErrorKind::InvalidInput, format!("{}: {}", s, e)))
+}
+
+/// Parse a single number or an inclusive range of numbers (e.g. `7` or `2-5`)
+///
+/// Both ends of the range must lie within `min` - `max`.
+fn parse_range(s: &str, min: u32, max: u32) -> std::io::Result<(u32, u32)> {
+    let (first, last) = match s.split_once('-') {
+        Some((first, last)) => (parse_number(first)?, parse_number(last)?),
+        None => (parse_number(s)?, parse_number(s)?),
+    };
+
+    if first > last {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("{}: empty range", s)));
+    }
+
+    let bounded = |n: usize| u32::try_from(n).ok().filter(|n| (min..=max).contains(n));
+    match (bounded(first), bounded(last)) {
+        (Some(first), Some(last)) => Ok((first, last)),
+        _ => Err(Error::new(ErrorKind::InvalidInput, format!("{}: out of range {} - {}", s, min, max))),
+    }
+}
+
+/// Print one tab separated line per entry (attributes, size, start cluster, last write, name)
+fn print_entry(e: &Entry) {
+    print_entry_path(e, &e.name());
+}
+
+/// Print one tab separated line per entry with its full path instead of its name
+fn print_entry_path(e: &Entry, path: &str) {
+    let written = e.written().map(|t| t.to_string()).unwrap_or_else(|| "-".to_string());
+    let deleted = if e.is_deleted() { " (deleted)" } else { "" };
+
+    println!("{}\t{}\t{}\t{}\t{}{}", e.flags(), e.size(), e.start().0, written, path, deleted);
+}
+
+/// List a directory (the root directory by default) or a single file
+fn ls(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+
+    let entries: Vec<Entry> = match matches.value_of("PATH").unwrap_or("/") {
+        "/" | "" => fat.root_dir().collect(),
+        path => {
+            let e = fat.stat(path)?;
+            if e.is_subdir_entry() {
+                fat.read_dir(&e)?.collect()
+            } else {
+                vec![e]
+            }
+        },
+    };
+
+    for e in &entries {
+        print_entry(e);
+    }
+
+    Ok(())
+}
+
+/// Write the content of a file (optionally including its slack) to stdout
+fn cat(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let entry = fat.stat(matches.value_of("PATH").unwrap())?;
+
+    if entry.is_subdir_entry() {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("{} is a directory", entry.name())));
+    }
+
+    let mut reader = if matches.is_present("slack") {
+        FileReader::with_slack(fat.fat(), &entry)
+    } else {
+        FileReader::new(fat.fat(), &entry)
+    };
+
+    io::copy(&mut reader, &mut io::stdout())?;
+    Ok(())
+}
+
+/// Write a flat listing of all entries as CSV or TSV to stdout
+fn list(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let rows = fat_listing::listing(&*fat, matches.is_present("hash"));
+    let delimiter = if matches.value_of("format") == Some("tsv") { b'\t' } else { b',' };
+
+    fat_listing::write(io::stdout(), &rows, delimiter)
+}
+
+/// Write a DFXML report of the volume to stdout
+fn dfxml(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let image = matches.value_of("INPUT").unwrap();
+
+    fat_dfxml::write(&mut io::stdout().lock(), &*fat, image, matches.is_present("hash"))
+}
+
+/// Hash every file and classify it using the given hash sets
+fn hash(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let mut db = HashDatabase::default();
+
+    for path in matches.values_of("known").into_iter().flatten() {
+        db.load_known(BufReader::new(File::open(path)?))?;
+    }
+
+    for path in matches.values_of("notable").into_iter().flatten() {
+        db.load_notable(BufReader::new(File::open(path)?))?;
+    }
+
+    let mut files = Vec::new();
+    for result in fat_hash::hash_files(&*fat, &db, !matches.is_present("no-deleted")) {
+        match result {
+            Ok(f) => files.push(f),
+            Err(e) => eprintln!("not hashed: {}", e),
+        }
+    }
+
+    for f in &files {
+        let deleted = if f.recovered { " (deleted)" } else { "" };
+        println!("{}\t{}\t{}\t{}\t{}{}", f.status, f.hashes.md5, f.hashes.sha1, f.hashes.sha256, f.path, deleted);
+    }
+
+    let notable = files.iter().filter(|f| f.status == Status::Notable).count();
+    let known = files.iter().filter(|f| f.status == Status::Known).count();
+    eprintln!("{} file(s) hashed, {} notable, {} known", files.len(), notable, known);
+
+    Ok(())
+}
+
+/// Display all details of a single directory entry given by path or address
+fn istat(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+
+    let (entry, path) = match matches.value_of("address") {
+        Some(address) => {
+            let address = parse_number(address)?;
+            if address.checked_add(32).map_or(true, |end| end > fat.fat().mem().len()) {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("address 0x{:x} lies outside of the volume", address)));
+            }
+
+            // entries found by a walk have their LFN entries attached and deleted names restored
+            match fat.walk(WalkOptions::default()).find(|item| item.entry.address() == Some(address)) {
+                Some(item) => (item.entry, Some(item.path)),
+                None => {
+                    let mut entry = Entry::new(&fat.fat().mem()[address..address + 32]);
+                    entry.add_address(address);
+                    entry.add_clusters(fat.fat().get_cluster_chain_in(fat.fat().active_fat(), entry.start()));
+                    (entry, None)
+                },
+            }
+        },
+        None => {
+            let path = matches.value_of("PATH").ok_or_else(|| Error::new(ErrorKind::InvalidInput, "either PATH or --address is required"))?;
+            (fat.stat(path)?, Some(path.to_string()))
+        },
+    };
+
+    fat_istat::istat(&mut io::stdout(), fat.fat(), &entry, path.as_deref())
+}
+
+/// Display the structure a byte offset, sector or cluster belongs to
+fn locate(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let base = fat.fat();
+    let value = parse_number(matches.value_of("OFFSET").unwrap())?;
+
+    let number = || u32::try_from(value).map_err(|_| Error::new(ErrorKind::InvalidInput, format!("{} is too large", value)));
+
+    let offset = if matches.is_present("sector") {
+        base.offset(&fat::Sector(number()?))
+    } else if matches.is_present("cluster") {
+        let cluster = number()?;
+        if !base.is_valid_cluster(cluster) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{} isn't a valid cluster", value)));
+        }
+        base.clusters_to_offsets(&[fat::Cluster(cluster)])[0]
+    } else {
+        value
+    };
+
+    let index = ClusterIndex::new(&*fat);
+    match index.locate(base, offset) {
+        Some(location) => println!("0x{:x} (sector {}): {}", offset, offset / base.bytes_per_sector() as usize, location),
+        None => return Err(Error::new(ErrorKind::InvalidInput, format!("offset 0x{:x} lies beyond the end of the image", offset))),
+    }
+
+    Ok(())
+}
+
+/// Write clusters, sectors or the clusters of a file as raw bytes or hexdump to stdout
+fn dump(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let base = fat.fat();
+    let hex = matches.is_present("hex");
+    let mut out = io::stdout().lock();
+
+    if let Some(range) = matches.value_of("sectors") {
+        let (first, last) = parse_range(range, 0, base.total_sectors().saturating_sub(1))?;
+        return fat_dump::dump_sectors(&mut out, base, first, last, hex);
+    }
+
+    let mut clusters: Vec<u32> = if let Some(range) = matches.value_of("clusters") {
+        let (first, last) = parse_range(range, 2, base.total_clusters())?;
+        (first..=last).collect()
+    } else if let Some(path) = matches.value_of("file") {
+        let e = fat.stat(path)?;
+        if fat_recover::is_unlinked(base, &e) {
+            fat_recover::presumed_clusters(base, &e).iter().map(|c| c.0).collect()
+        } else {
+            e.clusters().iter().flatten().map(|c| c.0).collect()
+        }
+    } else if matches.is_present("unallocated") {
+        (2..=base.total_clusters()).collect()
+    } else {
+        return Err(Error::new(ErrorKind::InvalidInput, "one of --clusters, --sectors, --file or --unallocated is required"));
+    };
+
+    if matches.is_present("unallocated") {
+        clusters.retain(|c| base.is_valid_cluster(*c) && fat_recover::is_free(base, *c));
+    }
+
+    fat_dump::dump_clusters(&mut out, base, &clusters, hex)
+}
+
+/// Analyse (and optionally extract) the slack of every file
+fn slack(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let files = fat_slack::slack_files(&*fat, !matches.is_present("no-deleted"));
+    let keywords: Vec<&str> = matches.values_of("keyword").into_iter().flatten().collect();
+    let searching = !keywords.is_empty() || matches.is_present("signatures");
+
+    if let Some(dir) = matches.value_of("extract") {
+        fs::create_dir_all(dir)?;
+    }
+
+    for s in &files {
+        let deleted = if s.deleted { " (deleted)" } else { "" };
+
+        if let Some(dir) = matches.value_of("extract") {
+            let name = format!("{:08x}-{}.slack", s.offset, s.path.rsplit('/').next().unwrap_or_default());
+            fs::write(Path::new(dir).join(name), &s.data)?;
+        }
+
+        if !searching {
+            println!("{}\t{}\t{}\t{}\t{}{}", s.non_zero(), s.data.len(), s.ram_len, s.cluster, s.path, deleted);
+            continue;
+        }
+
+        for keyword in &keywords {
+            let utf16: Vec<u8> = keyword.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+            for (encoding, pattern) in [("ascii", keyword.as_bytes()), ("utf-16le", &utf16[..])] {
+                for i in s.find(pattern) {
+                    println!("keyword\t0x{:x}\t{} ({})\t{}{}", s.offset + i, keyword, encoding, s.path, deleted);
+                }
+            }
+        }
+
+        if matches.is_present("signatures") {
+            for (i, signature) in s.signatures() {
+                println!("signature\t0x{:x}\t{}\t{}{}", s.offset + i, signature.name, s.path, deleted);
+            }
+        }
+    }
+
+    let non_zero = files.iter().filter(|s| s.non_zero() > 0).count();
+    eprintln!("{} file(s) with slack, {} with non-zero slack", files.len(), non_zero);
+
+    Ok(())
+}
+
+/// Search the volume for keywords and regular expressions
+fn search(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let ignore_case = matches.is_present("ignore-case");
+
+    let mut patterns = Vec::new();
+    for keyword in matches.values_of("keyword").into_iter().flatten() {
+        patterns.extend(Pattern::keyword(keyword, ignore_case));
+    }
+    for expr in matches.values_of("regex").into_iter().flatten() {
+        patterns.push(Pattern::regex(expr, ignore_case)?);
+    }
+
+    if patterns.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "at least one --keyword or --regex is required"));
+    }
+
+    let options = match matches.values_of("area") {
+        Some(areas) => {
+            let areas: Vec<&str> = areas.collect();
+            SearchOptions {
+                allocated: areas.contains(&"allocated"),
+                deleted: areas.contains(&"deleted"),
+                slack: areas.contains(&"slack"),
+                unallocated: areas.contains(&"unallocated"),
+            }
+        },
+        None => SearchOptions::default(),
+    };
+
+    let hits = fat_search::search(&*fat, &patterns, &options);
+
+    for hit in &hits {
+        let pattern = &patterns[hit.pattern];
+        println!("{}\t0x{:x}\t{}\t{} ({})\t{}\t{:?}", hit.area, hit.offset, cluster_ranges(&hit.clusters),
+                 pattern.name, pattern.encoding, hit.path.as_deref().unwrap_or("-"), hit.text(pattern));
+    }
+
+    eprintln!("{} hit(s)", hits.len());
+
+    Ok(())
+}
+
+/// Export all unallocated clusters as one file plus a mapping back onto the volume
+fn unallocated(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let output = matches.value_of("output").unwrap();
+    let map = match matches.value_of("map") {
+        Some(map) => map.to_string(),
+        None => format!("{}.map.csv", output),
+    };
+
+    let mut out = io::BufWriter::new(File::create(output)?);
+    let mapping = fat_dump::export_unallocated(&mut out, fat.fat())?;
+
+    let mut csv = csv::Writer::from_writer(File::create(&map)?);
+    for run in &mapping {
+        csv.serialize(run)?;
+    }
+    csv.flush()?;
+
+    let clusters: u32 = mapping.iter().map(|r| r.last_cluster - r.first_cluster + 1).sum();
+    eprintln!("{} cluster(s) in {} run(s) written to {}, mapping written to {}", clusters, mapping.len(), output, map);
+
+    Ok(())
+}
+
+/// Report data hidden in the regions of the volume that aren't used by the file system
+fn hidden(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let regions = fat_hidden::hidden_regions(fat.fat());
+
+    for region in &regions {
+        println!("{}", region);
+
+        if let (true, Some(first)) = (matches.is_present("hex"), region.first_non_zero) {
+            let window = &fat.fat().mem()[first..std::cmp::min(first + 256, region.offset + region.length)];
+            // don't show the zeros after the last non-zero byte
+            let len = window.iter().rposition(|b| *b != 0).map(|i| i + 1).unwrap_or(0);
+            print!("{}", fat_dump::hexdump(&window[..len], first));
+        }
+    }
+
+    let flagged = regions.iter().filter(|r| r.has_data()).count();
+    eprintln!("{} region(s) inspected, {} contain data", regions.len(), flagged);
+
+    Ok(())
+}
+
+/// List the entries hidden after the end of directory marker of every directory
+fn dirslack(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let entries = fat_dirslack::dir_slack(&*fat);
+
+    for s in &entries {
+        let e = &s.entry;
+        println!("{}\t0x{:x}\t{}\t{}\t{}\t{}/{}", s.kind, e.address().unwrap_or_default(), e.flags(), e.size(), e.start().0,
+                 s.dir.trim_end_matches('/'), e.name());
+    }
+
+    eprintln!("{} entr(y/ies) found in directory slack", entries.len());
+
+    Ok(())
+}
+
+/// List (and optionally extract) the content of orphaned directories
+fn orphans(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let base = fat.fat();
+    let files = fat_orphan::orphan_files(&*fat);
+    // directories with unsafe names (their content isn't extracted either)
+    let mut rejected: Vec<String> = Vec::new();
+
+    for item in &files {
+        print_entry_path(&item.entry, &item.path);
+
+        let dir = match matches.value_of("extract") {
+            Some(dir) => dir,
+            None => continue,
+        };
+        if rejected.iter().any(|r| item.path.starts_with(r)) {
+            continue;
+        }
+
+        // orphaned directories are listed with their `.` entry
+        let name = if item.entry.is_this_entry() { item.path.rsplit('/').next().unwrap_or_default().to_string() } else { item.entry.name() };
+        let target = match fat_recover::output_path(Path::new(dir), &item.path, &name) {
+            Ok(target) => target,
+            Err(e) => {
+                eprintln!("not extracted: {}", e);
+                rejected.push(format!("{}/", item.path));
+                continue;
+            },
+        };
+        if item.entry.is_subdir_entry() {
+            continue;
+        }
+
+        // the clusters of orphaned files are usually unlinked as well
+        let entry = if fat_recover::is_unlinked(base, &item.entry) {
+            match fat_recover::recover(base, &item.entry) {
+                Some(entry) => entry,
+                None => {
+                    eprintln!("not extracted: {} (clusters have been allocated again)", item.path);
+                    continue;
+                },
+            }
+        } else {
+            item.entry.clone()
+        };
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut FileReader::new(base, &entry), &mut File::create(target)?)?;
+    }
+
+    eprintln!("{} entr(y/ies) found in orphaned directories", files.len());
+
+    Ok(())
+}
+
+/// Recover a (deleted) file or directory tree into an output directory
+fn recover(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let base = fat.fat();
+    let path = matches.value_of("PATH").unwrap();
+    let entry = fat.stat(path)?;
+    let output = Path::new(matches.value_of("output").unwrap());
+
+    // paths within the output directory are relative to the parent of PATH
+    let parent = path.trim_end_matches('/').rsplit_once('/').map(|(p, _)| p).unwrap_or("");
+    let mut files = vec![WalkEntry { path: format!("{}/{}", parent, entry.name()), depth: 0, entry: entry.clone() }];
+    if entry.is_subdir_entry() {
+        files.extend(fat_recover::subtree(base, &entry, &files[0].path));
+    }
+
+    let (mut recovered, mut skipped) = (0, 0);
+    // directories with unsafe names (their content isn't recovered either)
+    let mut rejected: Vec<String> = Vec::new();
+    for item in &files {
+        if rejected.iter().any(|r| item.path.starts_with(r)) {
+            skipped += 1;
+            continue;
+        }
+
+        let target = match fat_recover::output_path(output, &item.path[parent.len()..], &item.entry.name()) {
+            Ok(target) => target,
+            Err(e) => {
+                eprintln!("not recovered: {}", e);
+                rejected.push(format!("{}/", item.path));
+                skipped += 1;
+                continue;
+            },
+        };
+
+        if item.entry.is_subdir_entry() {
+            fs::create_dir_all(target)?;
+            continue;
+        }
+
+        let entry = if fat_recover::is_unlinked(base, &item.entry) {
+            match fat_recover::recover(base, &item.entry) {
+                Some(entry) => entry,
+                None => {
+                    eprintln!("not recovered: {} (clusters have been allocated again)", item.path);
+                    skipped += 1;
+                    continue;
+                },
+            }
+        } else {
+            item.entry.clone()
+        };
+
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        io::copy(&mut FileReader::new(base, &entry), &mut File::create(target)?)?;
+        print_entry_path(&item.entry, &item.path);
+        recovered += 1;
+    }
+
+    eprintln!("{} file(s) recovered, {} skipped", recovered, skipped);
+
+    Ok(())
+}
+
+/// Assess how much of every deleted entry can still be recovered
+fn assess(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let assessments = fat_assess::assess(&*fat);
+
+    for a in &assessments {
+        println!("{}", a);
+    }
+
+    let count = |status| assessments.iter().filter(|a| a.status == status).count();
+    eprintln!("{} intact, {} partially overwritten, {} overwritten",
+              count(fat_assess::Status::Intact), count(fat_assess::Status::PartiallyOverwritten), count(fat_assess::Status::Overwritten));
+
+    Ok(())
+}
+
+/// Run a read-only consistency check and print one tab separated line per finding
+fn check(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let findings = fat.check();
+
+    for finding in &findings {
+        println!("{}", finding);
+    }
+
+    let errors = findings.iter().filter(|f| f.severity == Severity::Error).count();
+    eprintln!("{} finding(s), {} error(s)", findings.len(), errors);
+
+    if errors > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Show the changes that repair common corruption and write them if requested
+fn repair(matches: &ArgMatches) -> std::io::Result<()> {
+    let fat = open(matches)?;
+    let options = RepairOptions {
+        sync_fats: !matches.is_present("no-sync"),
+        save_lost: matches.is_present("save-lost"),
+        ..RepairOptions::default()
+    };
+    let repair = fat.repair(&options);
+
+    for finding in &repair.skipped {
+        eprintln!("not repaired: {}", finding);
+    }
+
+    match matches.value_of("log") {
+        Some(path) => fat_repair::write_log(&mut File::create(path)?, fat.fat().bytes_per_sector(), &repair.changes)?,
+        None => fat_repair::write_log(&mut io::stdout(), fat.fat().bytes_per_sector(), &repair.changes)?,
+    }
+
+    if matches.is_present("write") {
+        let mut file = OpenOptions::new().write(true).open(matches.value_of("INPUT").unwrap())?;
+        fat_repair::apply(&mut file, &repair.changes)?;
+        eprintln!("{} change(s) written", repair.changes.len());
+    } else {
+        eprintln!("dry run: {} change(s) planned, nothing written (use --write to apply them)", repair.changes.len());
+    }
+
+    Ok(())
+}
 
 fn main() -> std::io::Result<()> {
     let matches = App::new("Greasy")
         .version("0.1.0")
         .author("David Sugar (r4gus)")
         .about("Fat file system information and data recovery tool")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(Arg::with_name("info")
              .short("i")
              .long("info")
@@ -18,26 +639,458 @@ fn main() -> std::io::Result<()> {
              .short("t")
              .long("tree")
              .help("Display all directories in a tree like manner"))
+        .arg(Arg::with_name("max-depth")
+             .long("max-depth")
+             .takes_value(true)
+             .value_name("N")
+             .help("Don't display the tree below depth N"))
+        .arg(Arg::with_name("no-deleted")
+             .long("no-deleted")
+             .help("Don't display deleted entries in the tree"))
+        .arg(Arg::with_name("format")
+             .long("format")
+             .takes_value(true)
+             .possible_values(&["text", "json"])
+             .default_value("text")
+             .help("Output format of info and tree"))
+        .arg(Arg::with_name("compare")
+             .short("c")
+             .long("compare-fats")
+             .help("Compare all FAT tables and list divergent entries and the files they affect"))
+        .arg(Arg::with_name("backup")
+             .short("b")
+             .long("backup-boot")
+             .global(true)
+             .help("Interpret the volume using the backup boot sector (FAT32)"))
+        .arg(Arg::with_name("fat")
+             .short("f")
+             .long("fat")
+             .takes_value(true)
+             .value_name("N")
+             .global(true)
+             .help("Use FAT table N to resolve cluster chains (default: active FAT)"))
+        .arg(Arg::with_name("placeholder")
+             .long("placeholder")
+             .takes_value(true)
+             .value_name("C")
+             .global(true)
+             .help("Replaces the first character of deleted names that can't be restored (default: _)"))
         .arg(Arg::with_name("INPUT")
              .help("Fat volume to parse (e.g. fat-16.dd)")
              .required(true)
              .index(1))
+        .subcommand(SubCommand::with_name("ls")
+             .about("List the entries of a directory or show a single file")
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1))
+             .arg(Arg::with_name("PATH")
+                  .help("Path of a directory or file (default: /)")
+                  .index(2)))
+        .subcommand(SubCommand::with_name("cat")
+             .about("Write the content of a file to stdout")
+             .arg(Arg::with_name("slack")
+                  .short("s")
+                  .long("slack")
+                  .help("Continue past the end of the file up to the end of its last cluster"))
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1))
+             .arg(Arg::with_name("PATH")
+                  .help("Path of the file (e.g. /Documents/README.TXT)")
+                  .required(true)
+                  .index(2)))
+        .subcommand(SubCommand::with_name("list")
+             .about("List every entry of the volume as CSV or TSV")
+             .arg(Arg::with_name("format")
+                  .long("format")
+                  .takes_value(true)
+                  .possible_values(&["csv", "tsv"])
+                  .default_value("csv")
+                  .help("Output format"))
+             .arg(Arg::with_name("hash")
+                  .long("hash")
+                  .help("Add the MD5, SHA-1 and SHA-256 of every file"))
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1)))
+        .subcommand(SubCommand::with_name("dfxml")
+             .about("Write a Digital Forensics XML (DFXML) report")
+             .arg(Arg::with_name("hash")
+                  .long("hash")
+                  .help("Add the MD5, SHA-1 and SHA-256 of every file"))
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1)))
+        .subcommand(SubCommand::with_name("hash")
+             .about("Hash every file (MD5, SHA-1, SHA-256) and match it against hash sets")
+             .arg(Arg::with_name("known")
+                  .short("k")
+                  .long("known")
+                  .takes_value(true)
+                  .multiple(true)
+                  .number_of_values(1)
+                  .value_name("FILE")
+                  .help("Hash list (plain or NSRL CSV) of known-good files"))
+             .arg(Arg::with_name("notable")
+                  .short("n")
+                  .long("notable")
+                  .takes_value(true)
+                  .multiple(true)
+                  .number_of_values(1)
+                  .value_name("FILE")
+                  .help("Hash list (plain or NSRL CSV) of known-bad files"))
+             .arg(Arg::with_name("no-deleted")
+                  .long("no-deleted")
+                  .help("Don't hash recoverable deleted files"))
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1)))
+        .subcommand(SubCommand::with_name("istat")
+             .about("Display all details of a single directory entry")
+             .arg(Arg::with_name("address")
+                  .short("a")
+                  .long("address")
+                  .takes_value(true)
+                  .value_name("OFFSET")
+                  .conflicts_with("PATH")
+                  .help("Byte offset of the directory entry within the volume (e.g. 0xbc60)"))
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1))
+             .arg(Arg::with_name("PATH")
+                  .help("Path of the entry (e.g. /Documents/README.TXT)")
+                  .index(2)))
+        .subcommand(SubCommand::with_name("locate")
+             .about("Show which structure or file a byte offset, sector or cluster belongs to")
+             .arg(Arg::with_name("sector")
+                  .short("s")
+                  .long("sector")
+                  .conflicts_with("cluster")
+                  .help("Interpret OFFSET as a sector number"))
+             .arg(Arg::with_name("cluster")
+                  .short("c")
+                  .long("cluster")
+                  .help("Interpret OFFSET as a cluster number"))
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1))
+             .arg(Arg::with_name("OFFSET")
+                  .help("Byte offset within the volume, decimal or hexadecimal (e.g. 0xa800)")
+                  .required(true)
+                  .index(2)))
+        .subcommand(SubCommand::with_name("dump")
+             .about("Write the raw content of clusters, sectors or a file's clusters to stdout")
+             .arg(Arg::with_name("clusters")
+                  .short("c")
+                  .long("clusters")
+                  .takes_value(true)
+                  .value_name("RANGE")
+                  .conflicts_with_all(&["sectors", "file"])
+                  .help("Dump a cluster or a range of clusters (e.g. 2-5)"))
+             .arg(Arg::with_name("sectors")
+                  .short("s")
+                  .long("sectors")
+                  .takes_value(true)
+                  .value_name("RANGE")
+                  .conflicts_with_all(&["file", "unallocated"])
+                  .help("Dump a sector or a range of sectors (e.g. 0-3)"))
+             .arg(Arg::with_name("file")
+                  .short("p")
+                  .long("path")
+                  .takes_value(true)
+                  .value_name("PATH")
+                  .help("Dump all clusters of a file or directory (presumed clusters if deleted)"))
+             .arg(Arg::with_name("unallocated")
+                  .short("u")
+                  .long("unallocated")
+                  .help("Only dump unallocated clusters (all of them if no range is given)"))
+             .arg(Arg::with_name("hex")
+                  .short("x")
+                  .long("hex")
+                  .help("Render an annotated hexdump instead of the raw bytes"))
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1)))
+        .subcommand(SubCommand::with_name("slack")
+             .about("Analyse the slack space (RAM and file slack) of every file")
+             .arg(Arg::with_name("extract")
+                  .short("e")
+                  .long("extract")
+                  .takes_value(true)
+                  .value_name("DIR")
+                  .help("Write the slack of every file to DIR"))
+             .arg(Arg::with_name("keyword")
+                  .short("k")
+                  .long("keyword")
+                  .takes_value(true)
+                  .multiple(true)
+                  .number_of_values(1)
+                  .value_name("KEYWORD")
+                  .help("Search the slack for KEYWORD (ASCII and UTF-16LE)"))
+             .arg(Arg::with_name("signatures")
+                  .short("s")
+                  .long("signatures")
+                  .help("Search the slack for file signatures"))
+             .arg(Arg::with_name("no-deleted")
+                  .long("no-deleted")
+                  .help("Ignore the slack of deleted files"))
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1)))
+        .subcommand(SubCommand::with_name("search")
+             .about("Search files, deleted files, slack and unallocated clusters for keywords")
+             .arg(Arg::with_name("keyword")
+                  .short("k")
+                  .long("keyword")
+                  .takes_value(true)
+                  .multiple(true)
+                  .number_of_values(1)
+                  .value_name("KEYWORD")
+                  .help("Search for KEYWORD (ASCII and UTF-16LE)"))
+             .arg(Arg::with_name("regex")
+                  .short("e")
+                  .long("regex")
+                  .takes_value(true)
+                  .multiple(true)
+                  .number_of_values(1)
+                  .value_name("REGEX")
+                  .help("Search for a regular expression (ASCII/UTF-8)"))
+             .arg(Arg::with_name("ignore-case")
+                  .short("i")
+                  .long("ignore-case")
+                  .help("Match case-insensitively"))
+             .arg(Arg::with_name("area")
+                  .short("a")
+                  .long("area")
+                  .takes_value(true)
+                  .multiple(true)
+                  .number_of_values(1)
+                  .possible_values(&["allocated", "deleted", "slack", "unallocated"])
+                  .help("Only search the given area (default: all)"))
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1)))
+        .subcommand(SubCommand::with_name("unallocated")
+             .about("Export all unallocated clusters as one file (e.g. for carving)")
+             .arg(Arg::with_name("output")
+                  .short("o")
+                  .long("output")
+                  .takes_value(true)
+                  .value_name("FILE")
+                  .required(true)
+                  .help("Write the unallocated space to FILE"))
+             .arg(Arg::with_name("map")
+                  .short("m")
+                  .long("map")
+                  .takes_value(true)
+                  .value_name("FILE")
+                  .help("Write the mapping onto the volume to FILE (default: <output>.map.csv)"))
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1)))
+        .subcommand(SubCommand::with_name("hidden")
+             .about("Look for data hidden in regions that aren't used by the file system")
+             .arg(Arg::with_name("hex")
+                  .short("x")
+                  .long("hex")
+                  .help("Show a hexdump of up to 256 bytes starting at the first non-zero byte"))
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1)))
+        .subcommand(SubCommand::with_name("dirslack")
+             .about("List entries hidden after the end of directory marker")
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1)))
+        .subcommand(SubCommand::with_name("orphans")
+             .about("List orphaned directories found in unallocated space as $OrphanFiles")
+             .arg(Arg::with_name("extract")
+                  .short("e")
+                  .long("extract")
+                  .takes_value(true)
+                  .value_name("DIR")
+                  .help("Extract the files of the orphaned directories to DIR"))
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1)))
+        .subcommand(SubCommand::with_name("recover")
+             .about("Recover a deleted file or directory including all of its content")
+             .arg(Arg::with_name("output")
+                  .short("o")
+                  .long("output")
+                  .takes_value(true)
+                  .value_name("DIR")
+                  .required(true)
+                  .help("Directory to write the recovered files to"))
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1))
+             .arg(Arg::with_name("PATH")
+                  .help("Path of the file or directory (e.g. /Pictures/OLD)")
+                  .required(true)
+                  .index(2)))
+        .subcommand(SubCommand::with_name("assess")
+             .about("Assess whether the data of every deleted entry has been overwritten")
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to read (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1)))
+        .subcommand(SubCommand::with_name("check")
+             .about("Check the consistency of the volume (read-only)")
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to check (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1)))
+        .subcommand(SubCommand::with_name("repair")
+             .about("Repair the problems found by check")
+             .arg(Arg::with_name("write")
+                  .short("w")
+                  .long("write")
+                  .help("Write the changes to the volume (by default they are only shown)"))
+             .arg(Arg::with_name("save-lost")
+                  .short("s")
+                  .long("save-lost")
+                  .help("Save lost chains as FOUND.000/FILE0000.CHK instead of freeing them"))
+             .arg(Arg::with_name("no-sync")
+                  .long("no-sync")
+                  .help("Don't copy the active FAT to the other FAT tables"))
+             .arg(Arg::with_name("log")
+                  .short("l")
+                  .long("log")
+                  .takes_value(true)
+                  .value_name("FILE")
+                  .help("Write the change log to FILE instead of stdout"))
+             .arg(Arg::with_name("INPUT")
+                  .help("Fat volume to repair (e.g. fat-16.dd)")
+                  .required(true)
+                  .index(1)))
         .get_matches();
 
-    let file = File::open(matches.value_of("INPUT").unwrap())?;
-    let mem = unsafe { MmapOptions::new().map(&file)? };
+    if let Some(sub) = matches.subcommand_matches("ls") {
+        return ls(sub);
+    }
+
+    if let Some(sub) = matches.subcommand_matches("cat") {
+        return cat(sub);
+    }
+
+    if let Some(sub) = matches.subcommand_matches("list") {
+        return list(sub);
+    }
+
+    if let Some(sub) = matches.subcommand_matches("dfxml") {
+        return dfxml(sub);
+    }
+
+    if let Some(sub) = matches.subcommand_matches("hash") {
+        return hash(sub);
+    }
 
-    let fat = fat::Fat::new(mem);
+    if let Some(sub) = matches.subcommand_matches("istat") {
+        return istat(sub);
+    }
 
+    if let Some(sub) = matches.subcommand_matches("locate") {
+        return locate(sub);
+    }
+
+    if let Some(sub) = matches.subcommand_matches("dump") {
+        return dump(sub);
+    }
 
-    if matches.is_present("info") {
-        fat.info();
+    if let Some(sub) = matches.subcommand_matches("slack") {
+        return slack(sub);
     }
-    
-    if matches.is_present("tree") {
-        fat.tree();
+
+    if let Some(sub) = matches.subcommand_matches("search") {
+        return search(sub);
+    }
+
+    if let Some(sub) = matches.subcommand_matches("unallocated") {
+        return unallocated(sub);
+    }
+
+    if let Some(sub) = matches.subcommand_matches("hidden") {
+        return hidden(sub);
+    }
+
+    if let Some(sub) = matches.subcommand_matches("dirslack") {
+        return dirslack(sub);
+    }
+
+    if let Some(sub) = matches.subcommand_matches("orphans") {
+        return orphans(sub);
+    }
+
+    if let Some(sub) = matches.subcommand_matches("recover") {
+        return recover(sub);
+    }
+
+    if let Some(sub) = matches.subcommand_matches("assess") {
+        return assess(sub);
+    }
+
+    if let Some(sub) = matches.subcommand_matches("check") {
+        return check(sub);
+    }
+
+    if let Some(sub) = matches.subcommand_matches("repair") {
+        return repair(sub);
+    }
+
+    let fat = open(&matches)?;
+
+    let max_depth = match matches.value_of("max-depth") {
+        Some(n) => Some(n.parse::<usize>().map_err(|e| Error::new(ErrorKind::InvalidInput, e))?),
+        None => None,
+    };
+    let options = WalkOptions {
+        include_deleted: !matches.is_present("no-deleted"),
+        max_depth,
+        ..WalkOptions::default()
+    };
+
+    if matches.value_of("format") == Some("json") {
+        let mut doc = fat_json::Document::default();
+
+        if matches.is_present("info") {
+            doc.info = Some(fat.fat().volume_info());
+        }
+
+        if matches.is_present("tree") {
+            doc.tree = Some(fat_json::tree(&*fat, options));
+        }
+
+        println!("{}", serde_json::to_string_pretty(&doc)?);
+    } else {
+        if matches.is_present("info") {
+            fat.info();
+        }
+
+        if matches.is_present("tree") {
+            fat.tree(options);
+        }
+    }
+
+    if matches.is_present("compare") {
+        fat.compare_fats();
     }
-    
 
     Ok(())
 }